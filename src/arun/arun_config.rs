@@ -1,16 +1,344 @@
 #[allow(unused)]
 use {
+    super::{
+        interpolate::{self, ENVIRONMENT_FILES, ENV_FILE},
+        platform,
+        template::{self, EXTENDS},
+    },
     arunlib::{arun_error::ArunError, utils::IntervalTimer},
-    bollard::models::DeviceMapping,
+    bollard::models::{DeviceMapping, HostConfig},
     error_stack::{IntoReport, Report, Result, ResultExt},
     jlogger_tracing::{
         jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
     },
-    serde::{Deserialize, Serialize},
+    regex::Regex,
+    serde::{
+        de::{self, DeserializeOwned, DeserializeSeed, Deserializer, SeqAccess, Visitor},
+        Deserialize, Serialize,
+    },
     serde_json,
-    std::{fmt::Display, ops::Deref},
+    sha2::{Digest, Sha256},
+    std::{
+        collections::HashMap,
+        fmt::Display,
+        fs,
+        ops::Deref,
+        path::{Path, PathBuf},
+        str::FromStr,
+        time::Duration,
+    },
 };
 
+const FEATURES: [&str; 4] = ["gui", "gpu-render", "redis-server", "wayland"];
+const BIND_OPTIONS: [&str; 14] = [
+    "ro",
+    "rw",
+    "z",
+    "Z",
+    "nocopy",
+    "shared",
+    "rshared",
+    "slave",
+    "rslave",
+    "private",
+    "rprivate",
+    "consistent",
+    "cached",
+    "delegated",
+];
+
+// Linux capabilities as named by docker, without the CAP_ prefix.
+const CAPABILITIES: [&str; 42] = [
+    "ALL",
+    "AUDIT_CONTROL",
+    "AUDIT_READ",
+    "AUDIT_WRITE",
+    "BLOCK_SUSPEND",
+    "BPF",
+    "CHECKPOINT_RESTORE",
+    "CHOWN",
+    "DAC_OVERRIDE",
+    "DAC_READ_SEARCH",
+    "FOWNER",
+    "FSETID",
+    "IPC_LOCK",
+    "IPC_OWNER",
+    "KILL",
+    "LEASE",
+    "LINUX_IMMUTABLE",
+    "MAC_ADMIN",
+    "MAC_OVERRIDE",
+    "MKNOD",
+    "NET_ADMIN",
+    "NET_BIND_SERVICE",
+    "NET_BROADCAST",
+    "NET_RAW",
+    "PERFMON",
+    "SETFCAP",
+    "SETGID",
+    "SETPCAP",
+    "SETUID",
+    "SYS_ADMIN",
+    "SYS_BOOT",
+    "SYS_CHROOT",
+    "SYS_MODULE",
+    "SYS_NICE",
+    "SYS_PACCT",
+    "SYS_PTRACE",
+    "SYS_RAWIO",
+    "SYS_RESOURCE",
+    "SYS_TIME",
+    "SYS_TTY_CONFIG",
+    "SYSLOG",
+    "WAKE_ALARM",
+];
+const SECURITY_OPTS: [&str; 5] = [
+    "no-new-privileges",
+    "seccomp",
+    "apparmor",
+    "label",
+    "systempaths",
+];
+
+// The closest candidate to a misspelled name, if any is close enough.
+fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|c| (strsim::jaro_winkler(name, c), c))
+        .filter(|(score, _)| *score > 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, c)| c)
+}
+
+// Append the closest valid name to an error about an unknown field or variant.
+fn with_suggestion(msg: String) -> String {
+    let re = Regex::new(r"unknown (?:field|variant) `([^`]*)`, expected ([^\n]*)").unwrap();
+
+    let suggestion = re.captures(&msg).and_then(|c| {
+        let expected: Vec<&str> = c
+            .get(2)
+            .map_or("", |m| m.as_str())
+            .split('`')
+            .skip(1)
+            .step_by(2)
+            .collect();
+        suggest(&c[1], expected).map(|s| s.to_string())
+    });
+
+    match suggestion {
+        Some(s) => format!("{}, did you mean `{}`?", msg.trim_end(), s),
+        None => msg,
+    }
+}
+
+// Describe a serde_json error with its position.
+fn describe_json_error(e: &serde_json::Error) -> String {
+    let msg = e.to_string();
+    let msg = msg
+        .rsplit_once(" at line ")
+        .map_or(msg.as_str(), |(m, _)| m);
+
+    with_suggestion(format!("line {} column {}: {}", e.line(), e.column(), msg))
+}
+
+// The line and column of the first whole word occurrence of token in text.
+fn position(text: &str, token: &str) -> Option<(usize, usize)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+
+    text.match_indices(token)
+        .find(|(i, _)| {
+            !text[..*i].ends_with(is_word) && !text[i + token.len()..].starts_with(is_word)
+        })
+        .map(|(i, _)| {
+            let before = &text[..i];
+            let line = before.matches('\n').count() + 1;
+            let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+            (line, column)
+        })
+}
+
+// Point an error about a resolved app at the source of the name or value it quotes, the
+// config file text or one of the templates the app extends. A value coming from a
+// variable or an env file has no source to point to.
+fn locate_error(msg: String, text: &str, templates: &[PathBuf]) -> String {
+    let token = match msg.split('`').nth(1) {
+        Some(t) if !t.is_empty() => t,
+        _ => return msg,
+    };
+
+    if let Some((line, column)) = position(text, token) {
+        return format!("line {} column {}: {}", line, column, msg);
+    }
+
+    for t in templates {
+        let found = fs::read_to_string(t)
+            .ok()
+            .and_then(|text| position(&text, token));
+
+        if let Some((line, column)) = found {
+            return format!(
+                "template {} line {} column {}: {}",
+                t.display(),
+                line,
+                column,
+                msg
+            );
+        }
+    }
+
+    msg
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// The path of the seccomp profile a security option loads, if any.
+pub fn seccomp_profile(opt: &str) -> Option<&str> {
+    opt.strip_prefix("seccomp=")
+        .or_else(|| opt.strip_prefix("seccomp:"))
+        .filter(|p| *p != "unconfined")
+}
+
+fn parse_error(desc: String) -> Report<ArunError> {
+    Report::new(ArunError::InvalidValue).attach_printable(desc)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Display for ConfigFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = match self {
+            ConfigFormat::Json => "json",
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+        };
+
+        write!(f, "{}", output)
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = ArunError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ConfigFormat::Json),
+            "toml" => Ok(ConfigFormat::Toml),
+            "yaml" | "yml" => Ok(ConfigFormat::Yaml),
+            _ => Err(ArunError::InvalidValue),
+        }
+    }
+}
+
+impl ConfigFormat {
+    pub fn from_extension(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| ConfigFormat::from_str(e).ok())
+    }
+
+    // Parse the source without the config schema, e.g. to merge it with templates.
+    pub fn parse_value(self, text: &str) -> Result<serde_json::Value, ArunError> {
+        match self {
+            ConfigFormat::Json => {
+                serde_json::from_str(text).map_err(|e| parse_error(describe_json_error(&e)))
+            }
+            ConfigFormat::Toml => toml::from_str(text).map_err(|e| parse_error(e.to_string())),
+            ConfigFormat::Yaml => serde_yaml::from_str(text)
+                .map(yaml_to_json)
+                .map_err(|e| parse_error(e.to_string())),
+        }
+    }
+
+    // Deserialize the text, the errors are reported with their position and the closest
+    // valid name of an unknown field.
+    pub fn deserialize<T: DeserializeOwned>(self, text: &str) -> Result<T, ArunError> {
+        match self {
+            ConfigFormat::Json => {
+                serde_json::from_str(text).map_err(|e| parse_error(describe_json_error(&e)))
+            }
+            ConfigFormat::Toml => {
+                toml::from_str(text).map_err(|e| parse_error(with_suggestion(e.to_string())))
+            }
+            ConfigFormat::Yaml => {
+                serde_yaml::from_str(text).map_err(|e| parse_error(with_suggestion(e.to_string())))
+            }
+        }
+    }
+
+    // Pick the format by file extension, or by content if the extension is unknown.
+    pub fn detect(path: &Path, text: &str) -> Self {
+        if let Some(f) = ConfigFormat::from_extension(path) {
+            return f;
+        }
+
+        if serde_json::from_str::<serde_json::Value>(text).is_ok() {
+            ConfigFormat::Json
+        } else if toml::from_str::<toml::Table>(text).is_ok() {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Yaml
+        }
+    }
+}
+
+// serde_yaml keeps the enum tags like `!Tcp addr`, turn them into the externally tagged
+// form used by the other formats.
+fn yaml_to_json(value: serde_yaml::Value) -> serde_json::Value {
+    use {serde_json::Value as J, serde_yaml::Value as Y};
+
+    match value {
+        Y::Null => J::Null,
+        Y::Bool(b) => J::Bool(b),
+        Y::Number(n) => serde_json::to_value(n).unwrap_or(J::Null),
+        Y::String(s) => J::String(s),
+        Y::Sequence(s) => J::Array(s.into_iter().map(yaml_to_json).collect()),
+        Y::Mapping(m) => J::Object(
+            m.into_iter()
+                .map(|(k, v)| {
+                    let k = match k {
+                        Y::String(s) => s,
+                        k => serde_yaml::to_string(&k)
+                            .unwrap_or_default()
+                            .trim()
+                            .to_string(),
+                    };
+                    (k, yaml_to_json(v))
+                })
+                .collect(),
+        ),
+        Y::Tagged(t) => {
+            let mut m = serde_json::Map::new();
+            m.insert(
+                t.tag.to_string().trim_start_matches('!').to_string(),
+                yaml_to_json(t.value),
+            );
+            J::Object(m)
+        }
+    }
+}
+
+// A TOML document has to be a table, a manifest lists its apps as [[apps]].
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlManifest {
+    apps: Vec<ArunConfig>,
+}
+
+#[derive(Serialize)]
+struct TomlManifestRef<'a> {
+    apps: &'a [ArunConfig],
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 pub enum AppType {
     Sys,
@@ -28,14 +356,42 @@ impl Display for AppType {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct ArunDeviceMapping {
     path_on_host: String,
-    path_in_container: String,
-    cgroup_permissions: String,
+    // Same as path_on_host if not specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path_in_container: Option<String>,
+    // "rwm" if not specified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cgroup_permissions: Option<String>,
+}
+
+impl ArunDeviceMapping {
+    pub fn path_in_container(&self) -> &str {
+        self.path_in_container
+            .as_deref()
+            .unwrap_or(self.path_on_host.as_str())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl From<&ArunDeviceMapping> for DeviceMapping {
+    fn from(d: &ArunDeviceMapping) -> Self {
+        DeviceMapping {
+            path_on_host: Some(d.path_on_host.clone()),
+            path_in_container: Some(d.path_in_container().to_string()),
+            cgroup_permissions: Some(
+                d.cgroup_permissions
+                    .clone()
+                    .unwrap_or_else(|| "rwm".to_string()),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct PortBindingInfo {
     pub port: String,
     pub host: Vec<String>,
@@ -49,20 +405,868 @@ impl Deref for PortBindingInfo {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+// Check a bind spec of the form host-src:container-dest[:options].
+fn validate_bind(bind: &str) -> std::result::Result<(), String> {
+    let parts: Vec<&str> = bind.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err("expected host-src:container-dest[:options]".to_string());
+    }
+
+    let volume = Regex::new(r"^[a-zA-Z0-9][a-zA-Z0-9_.-]+$").unwrap();
+    if !parts[0].starts_with('/') && !volume.is_match(parts[0]) {
+        return Err(format!(
+            "`{}` is neither an absolute path nor a volume name",
+            parts[0]
+        ));
+    }
+
+    if !parts[1].starts_with('/') {
+        return Err(format!("`{}` is not an absolute path", parts[1]));
+    }
+
+    if let Some(options) = parts.get(2) {
+        for o in options.split(',') {
+            if !BIND_OPTIONS.contains(&o) {
+                let mut e = format!("unknown option `{}`", o);
+                if let Some(s) = suggest(o, BIND_OPTIONS) {
+                    e.push_str(&format!(", did you mean `{}`?", s));
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_capability(cap: &str) -> std::result::Result<(), String> {
+    let name = cap.to_uppercase();
+    let name = name.strip_prefix("CAP_").unwrap_or(&name);
+
+    if CAPABILITIES.contains(&name) {
+        return Ok(());
+    }
+
+    let mut e = format!("unknown capability `{}`", cap);
+    if let Some(s) = suggest(name, CAPABILITIES) {
+        e.push_str(&format!(", did you mean `{}`?", s));
+    }
+    Err(e)
+}
+
+fn validate_security_opt(opt: &str) -> std::result::Result<(), String> {
+    // Both key=value and the legacy key:value forms are accepted by docker.
+    let (key, value) = match opt.split_once(['=', ':']) {
+        Some((k, v)) => (k, Some(v)),
+        None => (opt, None),
+    };
+
+    if !SECURITY_OPTS.contains(&key) {
+        let mut e = format!("unknown security option `{}`", key);
+        if let Some(s) = suggest(key, SECURITY_OPTS) {
+            e.push_str(&format!(", did you mean `{}`?", s));
+        }
+        return Err(e);
+    }
+
+    match (key, value) {
+        ("no-new-privileges", None | Some("true") | Some("false")) => Ok(()),
+        ("no-new-privileges", Some(v)) => Err(format!("expected true or false, found `{}`", v)),
+        ("systempaths", Some("unconfined")) => Ok(()),
+        ("systempaths", _) => Err("only systempaths=unconfined is supported".to_string()),
+        (_, None) | (_, Some("")) => Err(format!("`{}` needs a value", key)),
+        _ => Ok(()),
+    }
+}
+
+fn validate_feature(feature: &str) -> std::result::Result<(), String> {
+    if FEATURES.contains(&feature) {
+        return Ok(());
+    }
+
+    let mut e = format!("unknown feature `{}`", feature);
+    if let Some(s) = suggest(feature, FEATURES) {
+        e.push_str(&format!(", did you mean `{}`?", s));
+    }
+    Err(e)
+}
+
+fn validate_absolute(path: &str) -> std::result::Result<(), String> {
+    if path.starts_with('/') {
+        Ok(())
+    } else {
+        Err(format!("`{}` is not an absolute path", path))
+    }
+}
+
+fn validate_digest(digest: &str) -> std::result::Result<(), String> {
+    let re = Regex::new(r"^sha256:[0-9a-f]{64}$").unwrap();
+    if re.is_match(digest) {
+        Ok(())
+    } else {
+        Err(format!(
+            "digest `{}` is not sha256: followed by 64 lowercase hex digits",
+            digest
+        ))
+    }
+}
+
+fn validate_platform(p: &str) -> std::result::Result<(), String> {
+    if platform::valid(p) {
+        Ok(())
+    } else {
+        Err(format!(
+            "platform `{}` is not os/arch[/variant], e.g. linux/arm64",
+            p
+        ))
+    }
+}
+
+fn validate_userns_mode(mode: &str) -> std::result::Result<(), String> {
+    if mode.is_empty() || mode == "host" {
+        Ok(())
+    } else {
+        Err(format!(
+            "userns_mode `{}` is not supported, only host is",
+            mode
+        ))
+    }
+}
+
+type Check = fn(&str) -> std::result::Result<(), String>;
+
+// The values are checked while deserializing, so that the invalid value is reported at
+// its position in the source by the parser of each format. The check runs inside the
+// string visitor, where the parsers still know the position of the value.
+#[derive(Clone, Copy)]
+struct Checked(Check);
+
+impl<'de> Visitor<'de> for Checked {
+    type Value = String;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<String, E> {
+        (self.0)(v).map_err(E::custom)?;
+        Ok(v.to_string())
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for Checked {
+    type Value = String;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> std::result::Result<String, D::Error> {
+        d.deserialize_str(self)
+    }
+}
+
+struct CheckedList(Check);
+
+impl<'de> Visitor<'de> for CheckedList {
+    type Value = Vec<String>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a list of strings")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut seq: A,
+    ) -> std::result::Result<Self::Value, A::Error> {
+        let mut list = vec![];
+        while let Some(v) = seq.next_element_seed(Checked(self.0))? {
+            list.push(v);
+        }
+        Ok(list)
+    }
+}
+
+fn checked_list<'de, D: Deserializer<'de>>(
+    d: D,
+    check: Check,
+) -> std::result::Result<Vec<String>, D::Error> {
+    d.deserialize_seq(CheckedList(check))
+}
+
+struct CheckedOption(Check);
+
+impl<'de> Visitor<'de> for CheckedOption {
+    type Value = Option<String>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a string")
+    }
+
+    fn visit_none<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> std::result::Result<Self::Value, E> {
+        Ok(None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> std::result::Result<Self::Value, D::Error> {
+        Checked(self.0).deserialize(d).map(Some)
+    }
+}
+
+fn checked_option<'de, D: Deserializer<'de>>(
+    d: D,
+    check: Check,
+) -> std::result::Result<Option<String>, D::Error> {
+    d.deserialize_option(CheckedOption(check))
+}
+
+fn de_features<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<String>, D::Error> {
+    checked_list(d, validate_feature)
+}
+
+fn de_binds<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<String>, D::Error> {
+    checked_list(d, |b| {
+        validate_bind(b).map_err(|e| format!("invalid bind `{}`, {}", b, e))
+    })
+}
+
+fn de_capabilities<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<String>, D::Error> {
+    checked_list(d, validate_capability)
+}
+
+fn de_security_opt<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<String>, D::Error> {
+    checked_list(d, |o| {
+        validate_security_opt(o).map_err(|e| format!("invalid security_opt `{}`, {}", o, e))
+    })
+}
+
+fn de_tmpfs<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Vec<String>, D::Error> {
+    checked_list(d, |t| {
+        validate_absolute(t).map_err(|e| format!("invalid tmpfs, {}", e))
+    })
+}
+
+fn de_absolute<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Option<String>, D::Error> {
+    checked_option(d, validate_absolute)
+}
+
+fn de_digest<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Option<String>, D::Error> {
+    checked_option(d, validate_digest)
+}
+
+fn de_platform<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<Option<String>, D::Error> {
+    checked_option(d, validate_platform)
+}
+
+fn de_userns_mode<'de, D: Deserializer<'de>>(
+    d: D,
+) -> std::result::Result<Option<String>, D::Error> {
+    checked_option(d, validate_userns_mode)
+}
+
+// A command line given either as a string with POSIX shell quoting or as an argv array.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ArunCmd {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+impl ArunCmd {
+    pub fn argv(&self) -> Result<Vec<String>, ArunError> {
+        match self {
+            ArunCmd::Shell(s) => shell_words::split(s)
+                .into_report()
+                .change_context(ArunError::InvalidValue)
+                .attach_printable(format!("Invalid command line: {}", s)),
+            ArunCmd::Argv(a) => Ok(a.clone()),
+        }
+    }
+}
+
+// Condition to be met before an app is started.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum DependsOn {
+    // The app with the given appid is running.
+    Started(String),
+    // The app with the given appid is running and healthy.
+    Healthy(String),
+    // A unix socket exists at the given host path.
+    SocketExists(String),
+}
+
+impl DependsOn {
+    pub fn appid(&self) -> Option<&str> {
+        match self {
+            DependsOn::Started(a) | DependsOn::Healthy(a) => Some(a),
+            DependsOn::SocketExists(_) => None,
+        }
+    }
+}
+
+impl Display for DependsOn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependsOn::Started(a) => write!(f, "{} started", a),
+            DependsOn::Healthy(a) => write!(f, "{} healthy", a),
+            DependsOn::SocketExists(p) => write!(f, "socket {} exists", p),
+        }
+    }
+}
+
+// Probe used to check whether a running app is healthy.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum HealthProbe {
+    // Run the command inside the container, exit code 0 means healthy.
+    Exec(Vec<String>),
+    // Connect to the given host:port.
+    Tcp(String),
+    // GET the given http:// url, a 2xx or 3xx status means healthy.
+    Http(String),
+    // The given host path exists.
+    FileExists(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct HealthCheck {
+    probe: HealthProbe,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interval: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timeout: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retries: Option<u32>,
+}
+
+impl HealthCheck {
+    pub fn probe(&self) -> &HealthProbe {
+        &self.probe
+    }
+
+    // Seconds between two probes.
+    pub fn interval(&self) -> u32 {
+        self.interval.unwrap_or(10)
+    }
+
+    // Seconds to wait for a probe before it is regarded as failed.
+    pub fn timeout(&self) -> u32 {
+        self.timeout.unwrap_or(3)
+    }
+
+    // Consecutive failures before the app is regarded as unhealthy.
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(3).max(1)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RestartMode {
+    Always,
+    OnFailure,
+    Never,
+}
+
+// What to do with an existing container created from another image or config, e.g. after
+// the version has been bumped.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DriftAction {
+    // Stop and remove the container, then create it from the config.
+    #[default]
+    Replace,
+    // Leave the container alone and fail the app.
+    Refuse,
+    // Keep the container as it is.
+    Adopt,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct RestartPolicy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mode: Option<RestartMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_retries: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backoff_base: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backoff_cap: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reset_window: Option<u32>,
+}
+
+impl RestartPolicy {
+    pub fn mode(&self) -> RestartMode {
+        self.mode.unwrap_or(RestartMode::Always)
+    }
+
+    // Restarts allowed before the app is quarantined.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(5)
+    }
+
+    // Delay in seconds before the n-th consecutive restart, doubled on every restart
+    // and capped by backoff_cap.
+    pub fn backoff(&self, n: u32) -> u32 {
+        let base = self.backoff_base.unwrap_or(1);
+        let cap = self.backoff_cap.unwrap_or(60);
+
+        base.saturating_mul(2_u32.saturating_pow(n.saturating_sub(1)))
+            .min(cap)
+    }
+
+    // Seconds an app has to keep running before its failure count is reset.
+    pub fn reset_window(&self) -> u32 {
+        self.reset_window.unwrap_or(300)
+    }
+
+    // An app which ran for the reset window before exiting starts counting its
+    // failures again.
+    pub fn resets_failures(&self, ran_for: Duration) -> bool {
+        ran_for >= Duration::from_secs(self.reset_window() as u64)
+    }
+}
+
+// A size in bytes, or with a b, k, m or g suffix as for docker run, e.g. "512m".
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum ByteSize {
+    Bytes(i64),
+    Text(String),
+}
+
+impl ByteSize {
+    pub fn bytes(&self) -> Result<i64, ArunError> {
+        let text = match self {
+            ByteSize::Bytes(b) => return Ok(*b),
+            ByteSize::Text(t) => t.trim().to_lowercase(),
+        };
+
+        let re = Regex::new(r"^(-?[0-9]+)([bkmg]?)b?$").unwrap();
+        let bytes = re.captures(&text).and_then(|c| {
+            let n = c[1].parse::<i64>().ok()?;
+            let unit: i64 = match &c[2] {
+                "k" => 1 << 10,
+                "m" => 1 << 20,
+                "g" => 1 << 30,
+                _ => 1,
+            };
+            n.checked_mul(unit)
+        });
+
+        bytes
+            .ok_or(ArunError::InvalidValue)
+            .into_report()
+            .attach_printable(format!("Invalid size `{}`, expected e.g. 512m or 1g", text))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ByteSize::Bytes(b) => write!(f, "{}", b),
+            ByteSize::Text(t) => write!(f, "{}", t),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Resources {
+    // Hard memory limit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory: Option<ByteSize>,
+    // Soft limit enforced when the host runs short of memory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_reservation: Option<ByteSize>,
+    // Memory plus swap, -1 for unlimited swap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memory_swap: Option<ByteSize>,
+    // Relative CPU weight, 1024 for apps without limits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_shares: Option<i64>,
+    // Microseconds of CPU time the app may use in each cpu_period.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_quota: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_period: Option<i64>,
+    // CPUs the app may run on, e.g. "2-3" or "0,2".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpuset_cpus: Option<String>,
+    // -1 for unlimited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pids_limit: Option<i64>,
+    // Relative block IO weight, from 10 to 1000.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    blkio_weight: Option<u16>,
+    // Size of /dev/shm.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shm_size: Option<ByteSize>,
+}
+
+// CPU numbers of a cpuset like "0-2,4".
+fn cpu_list(cpuset: &str) -> Vec<u32> {
+    cpuset
+        .split(',')
+        .flat_map(|r| {
+            let (first, last) = r.split_once('-').unwrap_or((r, r));
+            let first = first.parse::<u32>().unwrap_or(0);
+            let last = last.parse::<u32>().unwrap_or(first);
+            first..=last
+        })
+        .collect()
+}
+
+fn size(s: &Option<ByteSize>) -> Result<Option<i64>, ArunError> {
+    s.as_ref().map(|s| s.bytes()).transpose()
+}
+
+impl Resources {
+    // Limits not set are taken from defaults.
+    pub fn or(&self, defaults: &Resources) -> Resources {
+        // The swap and the reservation of the defaults go with their memory limit, an app
+        // setting its own memory does not inherit them.
+        let unset = Resources::default();
+        let memory_defaults = if self.memory.is_none() {
+            defaults
+        } else {
+            &unset
+        };
+
+        Resources {
+            memory: self.memory.clone().or_else(|| defaults.memory.clone()),
+            memory_reservation: self
+                .memory_reservation
+                .clone()
+                .or_else(|| memory_defaults.memory_reservation.clone()),
+            memory_swap: self
+                .memory_swap
+                .clone()
+                .or_else(|| memory_defaults.memory_swap.clone()),
+            cpu_shares: self.cpu_shares.or(defaults.cpu_shares),
+            cpu_quota: self.cpu_quota.or(defaults.cpu_quota),
+            cpu_period: self.cpu_period.or(defaults.cpu_period),
+            cpuset_cpus: self
+                .cpuset_cpus
+                .clone()
+                .or_else(|| defaults.cpuset_cpus.clone()),
+            pids_limit: self.pids_limit.or(defaults.pids_limit),
+            blkio_weight: self.blkio_weight.or(defaults.blkio_weight),
+            shm_size: self.shm_size.clone().or_else(|| defaults.shm_size.clone()),
+        }
+    }
+
+    // Check the limits against the ranges accepted by docker.
+    pub fn validate(&self) -> Result<(), ArunError> {
+        let invalid =
+            |desc: String| Err(Report::new(ArunError::InvalidValue).attach_printable(desc));
+
+        let memory = size(&self.memory)?;
+        let reservation = size(&self.memory_reservation)?;
+        let swap = size(&self.memory_swap)?;
+        let shm = size(&self.shm_size)?;
+
+        // Docker refuses a memory limit below 6 MiB.
+        if let Some(m) = memory.filter(|m| *m < 6 << 20) {
+            return invalid(format!("memory {} is less than the minimum of 6m", m));
+        }
+
+        if let (Some(m), Some(r)) = (memory, reservation) {
+            if r > m {
+                return invalid(format!(
+                    "memory_reservation {} is more than memory {}",
+                    r, m
+                ));
+            }
+        }
+
+        if let Some(s) = swap.filter(|s| *s != -1) {
+            match memory {
+                Some(m) if s >= m => {}
+                Some(m) => return invalid(format!("memory_swap {} is less than memory {}", s, m)),
+                None => return invalid("memory_swap is set without memory".to_string()),
+            }
+        }
+
+        if shm.is_some_and(|s| s <= 0) {
+            return invalid("shm_size must be positive".to_string());
+        }
+
+        if let Some(c) = self.cpu_shares.filter(|c| *c < 2) {
+            return invalid(format!("cpu_shares {} is less than the minimum of 2", c));
+        }
+
+        if let Some(q) = self.cpu_quota.filter(|q| *q < 1000) {
+            return invalid(format!("cpu_quota {} is less than the minimum of 1000", q));
+        }
+
+        if let Some(p) = self.cpu_period.filter(|p| !(1000..=1_000_000).contains(p)) {
+            return invalid(format!("cpu_period {} is not within 1000 and 1000000", p));
+        }
+
+        if let Some(c) = &self.cpuset_cpus {
+            let re = Regex::new(r"^[0-9]+(-[0-9]+)?(,[0-9]+(-[0-9]+)?)*$").unwrap();
+            if !re.is_match(c) {
+                return invalid(format!(
+                    "invalid cpuset_cpus `{}`, expected e.g. 0-1 or 0,2",
+                    c
+                ));
+            }
+        }
+
+        if let Some(p) = self.pids_limit.filter(|p| *p == 0 || *p < -1) {
+            return invalid(format!("pids_limit {} must be positive or -1", p));
+        }
+
+        if let Some(w) = self.blkio_weight.filter(|w| !(10..=1000).contains(w)) {
+            return invalid(format!("blkio_weight {} is not within 10 and 1000", w));
+        }
+
+        Ok(())
+    }
+
+    // CPUs the app may use on average, from cpu_quota and cpu_period.
+    fn cpus(&self) -> Option<f64> {
+        self.cpu_quota
+            .map(|q| q as f64 / self.cpu_period.unwrap_or(100_000) as f64)
+    }
+
+    // The limits above the caps, a limit not set is unlimited and above any cap.
+    pub fn exceeded(&self, caps: &Resources) -> Result<Vec<String>, ArunError> {
+        let mut exceeded = vec![];
+
+        let sizes = [
+            ("memory", &self.memory, &caps.memory),
+            ("memory_swap", &self.memory_swap, &caps.memory_swap),
+            ("shm_size", &self.shm_size, &caps.shm_size),
+        ];
+        for (name, value, cap) in sizes {
+            if let Some(c) = size(cap)? {
+                match size(value)?.filter(|v| *v != -1) {
+                    Some(v) if v <= c => {}
+                    Some(v) => exceeded.push(format!("{} {} is above {}", name, v, c)),
+                    None => exceeded.push(format!("{} is unlimited, the cap is {}", name, c)),
+                }
+            }
+        }
+
+        let numbers = [
+            ("cpu_shares", self.cpu_shares, caps.cpu_shares),
+            (
+                "pids_limit",
+                self.pids_limit.filter(|p| *p > 0),
+                caps.pids_limit,
+            ),
+        ];
+        for (name, value, cap) in numbers {
+            if let Some(c) = cap {
+                match value {
+                    Some(v) if v <= c => {}
+                    Some(v) => exceeded.push(format!("{} {} is above {}", name, v, c)),
+                    None => exceeded.push(format!("{} is unlimited, the cap is {}", name, c)),
+                }
+            }
+        }
+
+        if let Some(c) = caps.cpus() {
+            match self.cpus() {
+                Some(v) if v <= c => {}
+                Some(v) => exceeded.push(format!("{:.2} cpus are above {:.2}", v, c)),
+                None => exceeded.push(format!("cpu_quota is unlimited, the cap is {:.2} cpus", c)),
+            }
+        }
+
+        if let Some(c) = &caps.cpuset_cpus {
+            let allowed = cpu_list(c);
+            match &self.cpuset_cpus {
+                Some(v) if cpu_list(v).iter().all(|cpu| allowed.contains(cpu)) => {}
+                Some(v) => exceeded.push(format!("cpuset_cpus {} is not within {}", v, c)),
+                None => exceeded.push(format!("cpuset_cpus is not set, the cap is {}", c)),
+            }
+        }
+
+        Ok(exceeded)
+    }
+
+    // The part of the host config setting the limits.
+    pub fn host_config(&self) -> Result<HostConfig, ArunError> {
+        Ok(HostConfig {
+            memory: size(&self.memory)?,
+            memory_reservation: size(&self.memory_reservation)?,
+            memory_swap: size(&self.memory_swap)?,
+            cpu_shares: self.cpu_shares,
+            cpu_quota: self.cpu_quota,
+            cpu_period: self.cpu_period,
+            cpuset_cpus: self.cpuset_cpus.clone(),
+            pids_limit: self.pids_limit,
+            blkio_weight: self.blkio_weight,
+            shm_size: size(&self.shm_size)?,
+            ..Default::default()
+        })
+    }
+}
+
+// Limits applied to the apps of each type which do not set them in their config.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceDefaults {
+    #[serde(rename = "Sys", default)]
+    sys: Resources,
+    #[serde(rename = "User", default)]
+    user: Resources,
+}
+
+impl ResourceDefaults {
+    pub fn load(path: &Path) -> Result<Self, ArunError> {
+        let text = std::fs::read_to_string(path)
+            .into_report()
+            .change_context(ArunError::IOError)
+            .attach_printable(format!("Failed to read {}", path.display()))?;
+
+        let value = ConfigFormat::detect(path, &text).parse_value(&text)?;
+        let defaults: ResourceDefaults = serde_json::from_value(value)
+            .map_err(|e| parse_error(with_suggestion(e.to_string())))
+            .attach_printable(format!("Invalid resource defaults {}", path.display()))?;
+
+        for (app_type, r) in [
+            (AppType::Sys, &defaults.sys),
+            (AppType::User, &defaults.user),
+        ] {
+            r.validate().attach_printable(format!(
+                "Invalid resource defaults of {:?} apps in {}",
+                app_type,
+                path.display()
+            ))?;
+        }
+
+        Ok(defaults)
+    }
+
+    pub fn get(&self, app_type: AppType) -> &Resources {
+        match app_type {
+            AppType::Sys => &self.sys,
+            AppType::User => &self.user,
+        }
+    }
+}
+
+// How a new config of an app differs from the running one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigChange {
+    Unchanged,
+    // Only settings used by arun itself changed, the container is kept.
+    Runtime,
+    // The container has to be recreated to apply the config.
+    Create,
+}
+
+// Optional settings left to their default are not serialized, so that adding one does
+// not change the config hash of the existing containers.
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct ArunConfig {
     name: String,
     app_type: AppType,
     image: String,
     version: String,
+    // sha256:<hex> the image is pinned to, the image ID or the digest of its manifest.
+    #[serde(
+        default,
+        deserialize_with = "de_digest",
+        skip_serializing_if = "Option::is_none"
+    )]
+    digest: Option<String>,
+    // os/arch[/variant] of the image to pull, the host platform by default.
+    #[serde(
+        default,
+        deserialize_with = "de_platform",
+        skip_serializing_if = "Option::is_none"
+    )]
+    platform: Option<String>,
+    // Registry credentials in the format of the docker config.json, used before the
+    // environment and the docker config.json.
+    #[serde(
+        default,
+        deserialize_with = "de_absolute",
+        skip_serializing_if = "Option::is_none"
+    )]
+    credentials_file: Option<String>,
+    // docker save tarball, optionally compressed, loaded instead of pulling the image.
+    #[serde(
+        default,
+        deserialize_with = "de_absolute",
+        skip_serializing_if = "Option::is_none"
+    )]
+    image_archive: Option<String>,
     privilege: bool,
     network: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     port_bindings: Option<Vec<PortBindingInfo>>,
-    cmd: String,
+    cmd: ArunCmd,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entrypoint: Option<ArunCmd>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(deserialize_with = "de_binds")]
     binds: Vec<String>,
+    #[serde(deserialize_with = "de_features")]
     features: Vec<String>,
     environments: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    devices: Vec<ArunDeviceMapping>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    device_cgroup_rules: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     monitor_interval: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    depends_on: Vec<DependsOn>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    health_check: Option<HealthCheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restart_policy: Option<RestartPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resources: Option<Resources>,
+    #[serde(
+        default,
+        deserialize_with = "de_capabilities",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    cap_add: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "de_capabilities",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    cap_drop: Vec<String>,
+    // e.g. "no-new-privileges", "seccomp=/etc/arun/seccomp.json", "apparmor=arun-app"
+    #[serde(
+        default,
+        deserialize_with = "de_security_opt",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    security_opt: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    read_only_rootfs: bool,
+    // container-dest[:options], e.g. "/tmp:rw,size=64m"
+    #[serde(
+        default,
+        deserialize_with = "de_tmpfs",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    tmpfs: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "de_userns_mode",
+        skip_serializing_if = "Option::is_none"
+    )]
+    userns_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on_drift: Option<DriftAction>,
 }
 
 impl Default for ArunConfig {
@@ -72,40 +1276,267 @@ impl Default for ArunConfig {
             app_type: AppType::User,
             image: "Invalid".to_string(),
             version: "latest".to_string(),
+            digest: None,
+            platform: None,
+            credentials_file: None,
+            image_archive: None,
             privilege: false,
             network: "none".to_string(),
             port_bindings: None,
-            cmd: "Invalid".to_string(),
+            cmd: ArunCmd::Shell("Invalid".to_string()),
+            entrypoint: None,
+            working_dir: None,
+            user: None,
             binds: Vec::new(),
             features: Vec::new(),
             environments: Vec::new(),
+            devices: Vec::new(),
+            device_cgroup_rules: Vec::new(),
             monitor_interval: Some(1_u32),
+            depends_on: Vec::new(),
+            health_check: None,
+            restart_policy: None,
+            resources: None,
+            cap_add: Vec::new(),
+            cap_drop: Vec::new(),
+            security_opt: Vec::new(),
+            read_only_rootfs: false,
+            tmpfs: Vec::new(),
+            userns_mode: None,
+            on_drift: None,
         }
     }
 }
 
 impl ArunConfig {
-    pub fn parse(json: &str, monitor_interval_s: Option<u32>) -> Result<ArunConfig, ArunError> {
-        let mut config: ArunConfig = serde_json::from_str(json)
-            .into_report()
-            .change_context(ArunError::InvalidValue)
-            .attach_printable(format!("Failed to parse json string:\n {}", json))?;
+    // The app configs of a manifest as they are written, before any template is applied.
+    pub fn manifest_entries(
+        text: &str,
+        format: ConfigFormat,
+    ) -> Result<Vec<serde_json::Value>, ArunError> {
+        match (format, format.parse_value(text)?) {
+            (ConfigFormat::Toml, serde_json::Value::Object(mut m)) if m.contains_key("apps") => {
+                match m.remove("apps") {
+                    Some(serde_json::Value::Array(a)) if m.is_empty() => Ok(a),
+                    _ => Err(parse_error(
+                        "A toml manifest only contains [[apps]] tables".to_string(),
+                    )),
+                }
+            }
+            (_, serde_json::Value::Array(a)) => Ok(a),
+            (_, v) => Ok(vec![v]),
+        }
+    }
+
+    // A manifest is either a single app config or a list of app configs: a json array,
+    // a yaml sequence or the [[apps]] tables of a toml document.
+    // dir is the directory of the config file, the templates extended are searched from it.
+    pub fn parse_manifest(
+        text: &str,
+        format: ConfigFormat,
+        dir: &Path,
+        monitor_interval_s: Option<u32>,
+    ) -> Result<Vec<ArunConfig>, ArunError> {
+        let entries = ArunConfig::manifest_entries(text, format)?;
+
+        // Deserializing the source directly keeps the error positions. The resolved apps
+        // merge templates, variables and env files, their errors are located by what they
+        // quote.
+        let resolve = text.contains("${")
+            || entries.iter().any(|e| {
+                [EXTENDS, ENV_FILE, ENVIRONMENT_FILES]
+                    .iter()
+                    .any(|k| e.get(k).is_some())
+            });
 
-        if let Some(m) = monitor_interval_s {
-            config.monitor_interval = Some(m);
+        let mut configs: Vec<ArunConfig> = if resolve {
+            let mut configs = vec![];
+            for entry in entries {
+                let (entry, templates) = template::resolve_with_templates(entry, dir)?;
+                let entry = interpolate::resolve(entry, dir)?;
+                configs.push(serde_json::from_value(entry).map_err(|e| {
+                    parse_error(locate_error(
+                        with_suggestion(e.to_string()),
+                        text,
+                        &templates,
+                    ))
+                })?);
+            }
+            configs
+        } else {
+            ArunConfig::deserialize_manifest(text, format)?
+        };
+
+        for config in configs.iter_mut() {
+            if let Some(m) = monitor_interval_s {
+                config.monitor_interval = Some(m);
+            }
+
+            config.validate()?;
         }
 
-        if config.monitor_interval.is_none() {
-            config.monitor_interval = Some(1_u32);
+        Ok(configs)
+    }
+
+    fn deserialize_manifest(
+        text: &str,
+        format: ConfigFormat,
+    ) -> Result<Vec<ArunConfig>, ArunError> {
+        let configs = match format {
+            ConfigFormat::Json => {
+                if text.trim_start().starts_with('[') {
+                    format.deserialize(text)?
+                } else {
+                    vec![format.deserialize(text)?]
+                }
+            }
+            ConfigFormat::Toml => {
+                let table: toml::Table =
+                    toml::from_str(text).map_err(|e| parse_error(e.to_string()))?;
+
+                if table.contains_key("apps") {
+                    format.deserialize::<TomlManifest>(text)?.apps
+                } else {
+                    vec![format.deserialize(text)?]
+                }
+            }
+            ConfigFormat::Yaml => {
+                let value: serde_yaml::Value =
+                    serde_yaml::from_str(text).map_err(|e| parse_error(e.to_string()))?;
+
+                if value.is_sequence() {
+                    format.deserialize(text)?
+                } else {
+                    vec![format.deserialize(text)?]
+                }
+            }
+        };
+
+        Ok(configs)
+    }
+
+    // The settings used to create the container, without the ones applied in place.
+    fn creation_spec(&self) -> ArunConfig {
+        // A seccomp profile is passed to docker by content, an edited profile changes the
+        // container as a changed path does.
+        let security_opt = self
+            .security_opt
+            .iter()
+            .map(
+                |o| match seccomp_profile(o).and_then(|p| fs::read(p).ok()) {
+                    Some(content) => format!("{}@sha256:{}", o, sha256_hex(&content)),
+                    None => o.clone(),
+                },
+            )
+            .collect();
+
+        ArunConfig {
+            security_opt,
+            monitor_interval: None,
+            depends_on: Vec::new(),
+            health_check: None,
+            restart_policy: None,
+            on_drift: None,
+            credentials_file: None,
+            image_archive: None,
+            ..self.clone()
+        }
+    }
+
+    pub fn change(&self, new: &ArunConfig) -> ConfigChange {
+        if self == new {
+            return ConfigChange::Unchanged;
         }
 
-        Ok(config)
+        if self.creation_spec() == new.creation_spec() {
+            ConfigChange::Runtime
+        } else {
+            ConfigChange::Create
+        }
+    }
+
+    // sha256 of the creation spec, stamped on the container to find out later whether it
+    // was created from another config.
+    pub fn config_hash(&self) -> String {
+        sha256_hex(&serde_json::to_vec(&self.creation_spec()).unwrap_or_default())
+    }
+
+    pub fn serialize_manifest(
+        configs: &[ArunConfig],
+        format: ConfigFormat,
+    ) -> Result<String, ArunError> {
+        let output = match (format, configs) {
+            (ConfigFormat::Json, [c]) => serde_json::to_string_pretty(c).map_err(|e| e.to_string()),
+            (ConfigFormat::Json, _) => {
+                serde_json::to_string_pretty(configs).map_err(|e| e.to_string())
+            }
+            (ConfigFormat::Toml, [c]) => toml::to_string_pretty(c).map_err(|e| e.to_string()),
+            (ConfigFormat::Toml, _) => toml::to_string_pretty(&TomlManifestRef { apps: configs })
+                .map_err(|e| e.to_string()),
+            (ConfigFormat::Yaml, [c]) => serde_yaml::to_string(c).map_err(|e| e.to_string()),
+            (ConfigFormat::Yaml, _) => serde_yaml::to_string(configs).map_err(|e| e.to_string()),
+        };
+
+        output.map_err(|e| {
+            Report::new(ArunError::InvalidValue)
+                .attach_printable(format!("Failed to convert to {}: {}", format, e))
+        })
+    }
+
+    // Check the values which are not verified by deserializing. The single values are
+    // checked while deserializing, see Checked, these checks need the whole config.
+    fn validate(&self) -> Result<(), ArunError> {
+        let appid = self.appid();
+
+        self.cmd()
+            .attach_printable(format!("Invalid cmd of {}", appid))?;
+        self.entrypoint()
+            .attach_printable(format!("Invalid entrypoint of {}", appid))?;
+
+        self.resources()
+            .validate()
+            .attach_printable(format!("Invalid resources of {}", appid))?;
+
+        Ok(())
+    }
+
+    // Fill the limits not set by the app with the defaults of its type.
+    pub fn apply_resource_defaults(
+        &mut self,
+        defaults: &ResourceDefaults,
+    ) -> Result<(), ArunError> {
+        let resources = self.resources().or(defaults.get(self.app_type));
+        resources.validate().attach_printable(format!(
+            "Invalid resources of {} with the defaults of {:?} apps",
+            self.appid(),
+            self.app_type
+        ))?;
+
+        self.resources = if resources == Resources::default() {
+            None
+        } else {
+            Some(resources)
+        };
+
+        Ok(())
+    }
+
+    pub fn resources(&self) -> Resources {
+        self.resources.clone().unwrap_or_default()
     }
 
     pub fn image(&self) -> String {
         format!("{}:{}", self.image, self.version)
     }
 
+    // The registry host of the image, docker.io if it is not given in the image name.
+    pub fn image_registry(&self) -> &str {
+        match self.image.split_once('/') {
+            Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+            _ => "docker.io",
+        }
+    }
+
     pub fn image_name(&self) -> &str {
         &self.image
     }
@@ -114,26 +1545,51 @@ impl ArunConfig {
         &self.version
     }
 
+    pub fn digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    pub fn credentials_file(&self) -> Option<&str> {
+        self.credentials_file.as_deref()
+    }
+
+    pub fn image_archive(&self) -> Option<&str> {
+        self.image_archive.as_deref()
+    }
+
+    pub fn platform(&self) -> &str {
+        self.platform
+            .as_deref()
+            .unwrap_or_else(|| platform::host_platform())
+    }
+
     pub fn appid(&self) -> String {
         format!("{}.{}", self.app_type, self.name)
     }
 
+    pub fn app_type(&self) -> AppType {
+        self.app_type
+    }
+
     pub fn network(&self) -> &str {
         jdebug!("network: {}", self.network);
         &self.network
     }
 
-    pub fn cmd(&self) -> Vec<String> {
-        let mut cmd: Vec<String> = self
-            .cmd
-            .as_str()
-            .rsplit(' ')
-            .into_iter()
-            .map(|a| a.to_string())
-            .collect();
+    pub fn cmd(&self) -> Result<Vec<String>, ArunError> {
+        self.cmd.argv()
+    }
 
-        cmd.reverse();
-        cmd
+    pub fn entrypoint(&self) -> Result<Option<Vec<String>>, ArunError> {
+        self.entrypoint.as_ref().map(|e| e.argv()).transpose()
+    }
+
+    pub fn working_dir(&self) -> Option<&str> {
+        self.working_dir.as_deref()
+    }
+
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_deref()
     }
 
     pub fn environment(&self) -> Vec<String> {
@@ -144,14 +1600,78 @@ impl ArunConfig {
         self.privilege
     }
 
+    // Settings which are valid but weaken the isolation of a user app.
+    pub fn security_warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+
+        if self.app_type == AppType::User {
+            if self.privilege {
+                warnings.push(format!("User app {} asks to run privileged", self.appid()));
+            }
+
+            if self.cap_add.iter().any(|c| c.eq_ignore_ascii_case("ALL")) {
+                warnings.push(format!(
+                    "User app {} asks for all capabilities",
+                    self.appid()
+                ));
+            }
+        }
+
+        warnings
+    }
+
+    pub fn cap_add(&self) -> &[String] {
+        &self.cap_add
+    }
+
+    pub fn cap_drop(&self) -> &[String] {
+        &self.cap_drop
+    }
+
+    pub fn security_opt(&self) -> &[String] {
+        &self.security_opt
+    }
+
+    pub fn read_only_rootfs(&self) -> bool {
+        self.read_only_rootfs
+    }
+
+    // Mount points of the tmpfs with their mount options.
+    pub fn tmpfs(&self) -> HashMap<String, String> {
+        self.tmpfs
+            .iter()
+            .map(|t| match t.split_once(':') {
+                Some((path, options)) => (path.to_string(), options.to_string()),
+                None => (t.to_string(), String::new()),
+            })
+            .collect()
+    }
+
+    pub fn userns_mode(&self) -> Option<&str> {
+        self.userns_mode.as_deref()
+    }
+
     pub fn binds(&self) -> Vec<&str> {
         self.binds.iter().map(|s| s.as_str()).collect()
     }
 
+    pub fn devices(&self) -> &[ArunDeviceMapping] {
+        &self.devices
+    }
+
+    pub fn device_cgroup_rules(&self) -> &[String] {
+        &self.device_cgroup_rules
+    }
+
     pub fn gui(&self) -> bool {
         self.features.iter().any(|f| f.as_str() == "gui")
     }
 
+    // Access to the GPU render nodes only, for apps without display output.
+    pub fn gpu_render(&self) -> bool {
+        self.features.iter().any(|f| f.as_str() == "gpu-render")
+    }
+
     pub fn redis_server(&self) -> bool {
         self.features.iter().any(|f| f.as_str() == "redis-server")
     }
@@ -165,6 +1685,576 @@ impl ArunConfig {
     }
 
     pub fn monitor_interval(&self) -> u32 {
-        self.monitor_interval.unwrap()
+        self.monitor_interval.unwrap_or(1)
+    }
+
+    pub fn depends_on(&self) -> &[DependsOn] {
+        &self.depends_on
+    }
+
+    pub fn health_check(&self) -> Option<&HealthCheck> {
+        self.health_check.as_ref()
+    }
+
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy.clone().unwrap_or_default()
+    }
+
+    pub fn on_drift(&self) -> DriftAction {
+        self.on_drift.unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &str = r#"{
+        "name": "app",
+        "app_type": "User",
+        "image": "joukan/app",
+        "version": "0.1",
+        "privilege": false,
+        "network": "none",
+        "cmd": ["/bin/app"],
+        "binds": [],
+        "features": [],
+        "environments": []
+    }"#;
+
+    fn config(extra: serde_json::Value) -> ArunConfig {
+        let mut value: serde_json::Value = serde_json::from_str(APP).unwrap();
+        if let (Some(v), serde_json::Value::Object(extra)) = (value.as_object_mut(), extra) {
+            v.extend(extra);
+        }
+
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn resources(json: &str) -> Resources {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn restart_policy(json: &str) -> RestartPolicy {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn suggestions_for_unknown_names() {
+        assert_eq!(
+            with_suggestion(
+                "unknown field `featues`, expected `features` or `environments`".to_string()
+            ),
+            "unknown field `featues`, expected `features` or `environments`, did you mean `features`?"
+        );
+        assert_eq!(
+            with_suggestion("unknown variant `Usr`, expected `Sys` or `User`\n".to_string()),
+            "unknown variant `Usr`, expected `Sys` or `User`, did you mean `User`?"
+        );
+
+        // Nothing close enough, or not about an unknown name.
+        let msg = "unknown field `xyz`, expected `features` or `environments`".to_string();
+        assert_eq!(with_suggestion(msg.clone()), msg);
+        let msg = "invalid type: string \"a\", expected a boolean".to_string();
+        assert_eq!(with_suggestion(msg.clone()), msg);
+    }
+
+    #[test]
+    fn parse_errors_have_position_and_suggestion() {
+        let text = APP.replace(r#""features": []"#, r#""featues": []"#);
+        let e = ConfigFormat::Json
+            .deserialize::<ArunConfig>(&text)
+            .unwrap_err();
+        let e = format!("{:?}", e);
+        assert!(e.contains("line 10 column"), "{}", e);
+        assert!(e.contains("did you mean `features`?"), "{}", e);
+
+        let text = APP.replace(r#""features": []"#, r#""features": ["wayalnd"]"#);
+        let e = format!(
+            "{:?}",
+            ConfigFormat::Json
+                .deserialize::<ArunConfig>(&text)
+                .unwrap_err()
+        );
+        assert!(e.contains("line 10 column"), "{}", e);
+        assert!(
+            e.contains("unknown feature `wayalnd`, did you mean `wayland`?"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn positions_of_whole_words() {
+        let text = "{\n  \"features\": [\"gui\", \"gui-x\"],\n  \"x\": \"wayland\"\n}";
+
+        assert_eq!(position(text, "features"), Some((2, 4)));
+        assert_eq!(position(text, "wayland"), Some((3, 9)));
+        assert_eq!(position(text, "gui"), Some((2, 17)));
+        assert_eq!(position(text, "gui-"), None);
+        assert_eq!(position(text, "way"), None);
+    }
+
+    #[test]
+    fn resolved_errors_point_at_their_source() {
+        let dir = std::env::temp_dir().join(format!("arun-locate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join(template::TEMPLATE_DIR)).unwrap();
+
+        let template = dir.join(template::TEMPLATE_DIR).join("base.json");
+        fs::write(
+            &template,
+            r#"{
+  "image": "joukan/app",
+  "version": "0.1",
+  "privilege": false,
+  "network": "none",
+  "featues": [],
+  "binds": [],
+  "environments": []
+}"#,
+        )
+        .unwrap();
+
+        let app = r#"{
+  "extends": "base",
+  "name": "app",
+  "app_type": "User",
+  "cmd": "/bin/app"
+}"#;
+        let in_template = ArunConfig::parse_manifest(app, ConfigFormat::Json, &dir, None);
+
+        fs::write(&template, APP).unwrap();
+        let app = r#"{
+  "extends": "base",
+  "features": ["wayalnd"]
+}"#;
+        let in_app = ArunConfig::parse_manifest(app, ConfigFormat::Json, &dir, None);
+
+        // Values from variables have no source.
+        std::env::set_var("ARUN_TEST_FEATURE", "gpu-rendr");
+        let app = r#"{
+  "extends": "base",
+  "features": ["${ARUN_TEST_FEATURE}"]
+}"#;
+        let in_variable = ArunConfig::parse_manifest(app, ConfigFormat::Json, &dir, None);
+        let _ = fs::remove_dir_all(&dir);
+
+        let e = format!("{:?}", in_template.unwrap_err());
+        let expected = format!(
+            "template {} line 6 column 4: unknown field `featues`",
+            template.display()
+        );
+        assert!(e.contains(&expected), "{}", e);
+        assert!(e.contains("did you mean `features`?"), "{}", e);
+
+        let e = format!("{:?}", in_app.unwrap_err());
+        assert!(
+            e.contains("line 3 column 17: unknown feature `wayalnd`, did you mean `wayland`?"),
+            "{}",
+            e
+        );
+
+        let e = format!("{:?}", in_variable.unwrap_err());
+        assert!(e.contains("unknown feature `gpu-rendr`"), "{}", e);
+        assert!(!e.contains("line"), "{}", e);
+    }
+
+    #[test]
+    fn bind_specs() {
+        for bind in [
+            "/tmp:/tmp",
+            "/run/user/1000:/run/user/1000:ro",
+            "data-vol:/data:rw,z",
+            "/dev/shm:/dev/shm:rshared,nocopy",
+        ] {
+            assert_eq!(validate_bind(bind), Ok(()), "{}", bind);
+        }
+
+        for (bind, error) in [
+            ("/tmp", "expected host-src:container-dest[:options]"),
+            (
+                "/a:/b:ro:extra",
+                "expected host-src:container-dest[:options]",
+            ),
+            (
+                "tmp/x:/tmp",
+                "`tmp/x` is neither an absolute path nor a volume name",
+            ),
+            ("/tmp:tmp", "`tmp` is not an absolute path"),
+            ("/tmp:/tmp:r", "unknown option `r`, did you mean `rw`?"),
+            (
+                "/tmp:/tmp:ro,rslve",
+                "unknown option `rslve`, did you mean `rslave`?",
+            ),
+            ("/tmp:/tmp:readonly", "unknown option `readonly`"),
+        ] {
+            assert_eq!(validate_bind(bind), Err(error.to_string()), "{}", bind);
+        }
+    }
+
+    #[test]
+    fn security_opts() {
+        for opt in [
+            "no-new-privileges",
+            "no-new-privileges:true",
+            "no-new-privileges=false",
+            "seccomp=unconfined",
+            "apparmor:docker-default",
+            "label=type:svirt_apache_t",
+            "systempaths=unconfined",
+        ] {
+            assert_eq!(validate_security_opt(opt), Ok(()), "{}", opt);
+        }
+
+        for (opt, error) in [
+            (
+                "no-new-privilege",
+                "unknown security option `no-new-privilege`, did you mean `no-new-privileges`?",
+            ),
+            (
+                "secomp=unconfined",
+                "unknown security option `secomp`, did you mean `seccomp`?",
+            ),
+            ("selinux=on", "unknown security option `selinux`"),
+            (
+                "no-new-privileges=yes",
+                "expected true or false, found `yes`",
+            ),
+            ("systempaths", "only systempaths=unconfined is supported"),
+            ("seccomp", "`seccomp` needs a value"),
+            ("apparmor=", "`apparmor` needs a value"),
+        ] {
+            assert_eq!(
+                validate_security_opt(opt),
+                Err(error.to_string()),
+                "{}",
+                opt
+            );
+        }
+    }
+
+    #[test]
+    fn backoff_grows_to_the_cap() {
+        let policy = restart_policy("{}");
+        let delays: Vec<u32> = (1..=8).map(|n| policy.backoff(n)).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+
+        let policy = restart_policy(r#"{"backoff_base": 3, "backoff_cap": 20}"#);
+        let delays: Vec<u32> = (1..=5).map(|n| policy.backoff(n)).collect();
+        assert_eq!(delays, [3, 6, 12, 20, 20]);
+
+        // No overflow for long failure runs.
+        assert_eq!(policy.backoff(u32::MAX), 20);
+        assert_eq!(policy.backoff(0), 3);
+    }
+
+    #[test]
+    fn failures_reset_after_the_window() {
+        let policy = restart_policy("{}");
+        assert!(!policy.resets_failures(Duration::from_secs(299)));
+        assert!(policy.resets_failures(Duration::from_secs(300)));
+
+        let policy = restart_policy(r#"{"reset_window": 10}"#);
+        assert!(!policy.resets_failures(Duration::from_millis(9_999)));
+        assert!(policy.resets_failures(Duration::from_secs(11)));
+    }
+
+    #[test]
+    fn config_hash_covers_the_seccomp_profile() {
+        let profile =
+            std::env::temp_dir().join(format!("arun-seccomp-{}.json", std::process::id()));
+        let app = config(serde_json::json!({
+            "security_opt": [format!("seccomp={}", profile.display()), "no-new-privileges"]
+        }));
+
+        fs::write(&profile, r#"{"defaultAction": "SCMP_ACT_ERRNO"}"#).unwrap();
+        let first = app.config_hash();
+        let again = app.config_hash();
+        fs::write(&profile, r#"{"defaultAction": "SCMP_ACT_ALLOW"}"#).unwrap();
+        let edited = app.config_hash();
+        let _ = fs::remove_file(&profile);
+
+        assert_eq!(first, again);
+        assert_ne!(first, edited);
+
+        assert_eq!(seccomp_profile("seccomp:/etc/p.json"), Some("/etc/p.json"));
+        assert_eq!(seccomp_profile("seccomp=unconfined"), None);
+        assert_eq!(seccomp_profile("apparmor=docker-default"), None);
+    }
+
+    #[test]
+    fn config_hash_skips_default_settings() {
+        let spec = serde_json::to_string(&config(serde_json::json!({})).creation_spec()).unwrap();
+
+        assert_eq!(
+            spec,
+            r#"{"name":"app","app_type":"User","image":"joukan/app","version":"0.1","privilege":false,"network":"none","cmd":["/bin/app"],"binds":[],"features":[],"environments":[]}"#
+        );
+        assert_eq!(
+            config(serde_json::json!({})).config_hash(),
+            config(serde_json::json!({
+                "devices": [],
+                "cap_add": [],
+                "security_opt": [],
+                "read_only_rootfs": false,
+                "tmpfs": []
+            }))
+            .config_hash()
+        );
+    }
+
+    #[test]
+    fn config_hash_ignores_runtime_settings() {
+        let hash = config(serde_json::json!({})).config_hash();
+
+        assert_eq!(hash.len(), 64);
+        assert_eq!(
+            hash,
+            config(serde_json::json!({
+                "monitor_interval": 5,
+                "restart_policy": {"mode": "Never"},
+                "on_drift": "Adopt"
+            }))
+            .config_hash()
+        );
+        assert_ne!(
+            hash,
+            config(serde_json::json!({"cmd": ["/bin/app", "-v"]})).config_hash()
+        );
+        assert_ne!(
+            hash,
+            config(serde_json::json!({"read_only_rootfs": true})).config_hash()
+        );
+    }
+
+    #[test]
+    fn digests() {
+        let hex = "0123456789abcdef".repeat(4);
+
+        assert!(validate_digest(&format!("sha256:{}", hex)).is_ok());
+        assert!(validate_digest(&hex).is_err());
+        assert!(validate_digest(&format!("sha512:{}", hex)).is_err());
+        assert!(validate_digest(&format!("sha256:{}", hex.to_uppercase())).is_err());
+        assert!(validate_digest(&format!("sha256:{}0", hex)).is_err());
+        assert!(validate_digest(&format!("sha256:{}", &hex[1..])).is_err());
+    }
+
+    #[test]
+    fn digest_is_checked_when_parsed() {
+        let hex = "0123456789abcdef".repeat(4);
+        let text = APP.replace(
+            r#""version": "0.1","#,
+            r#""version": "0.1", "digest": "sha256:xyz","#,
+        );
+
+        assert!(ArunConfig::deserialize_manifest(&text, ConfigFormat::Json).is_err());
+        assert!(ArunConfig::deserialize_manifest(
+            &text.replace("sha256:xyz", &format!("sha256:{}", hex)),
+            ConfigFormat::Json
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn image_registry_of_image_names() {
+        let registry = |image: &str| {
+            config(serde_json::json!({ "image": image }))
+                .image_registry()
+                .to_string()
+        };
+
+        assert_eq!(registry("redis"), "docker.io");
+        assert_eq!(registry("joukan/app"), "docker.io");
+        assert_eq!(registry("ghcr.io/joukan/app"), "ghcr.io");
+        assert_eq!(registry("registry.local:5000/app"), "registry.local:5000");
+        assert_eq!(registry("localhost/app"), "localhost");
+    }
+
+    // The printable attachments of an error, outermost first.
+    fn messages(e: Report<ArunError>) -> Vec<String> {
+        e.frames()
+            .filter_map(|f| f.downcast_ref::<String>().cloned())
+            .collect()
+    }
+
+    fn invalid(json: &str) -> String {
+        messages(resources(json).validate().unwrap_err()).join("\n")
+    }
+
+    #[test]
+    fn byte_sizes() {
+        let bytes = |s: &str| ByteSize::Text(s.to_string()).bytes();
+
+        assert_eq!(ByteSize::Bytes(1024).bytes().unwrap(), 1024);
+        assert_eq!(bytes("100").unwrap(), 100);
+        assert_eq!(bytes("100b").unwrap(), 100);
+        assert_eq!(bytes("4k").unwrap(), 4 << 10);
+        assert_eq!(bytes("4kb").unwrap(), 4 << 10);
+        assert_eq!(bytes("512m").unwrap(), 512 << 20);
+        assert_eq!(bytes(" 512M ").unwrap(), 512 << 20);
+        assert_eq!(bytes("2g").unwrap(), 2 << 30);
+        assert_eq!(bytes("-1").unwrap(), -1);
+
+        // Overflowing i64 with or without the unit.
+        assert!(bytes("8589934592g").is_err());
+        assert!(bytes("9223372036854775808").is_err());
+
+        for text in ["", "m", "1.5g", "1t", "1 g", "1gbb", "0x10", "512mi"] {
+            let e = messages(bytes(text).unwrap_err());
+            assert_eq!(
+                e,
+                [format!(
+                    "Invalid size `{}`, expected e.g. 512m or 1g",
+                    text.to_lowercase()
+                )],
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn resources_are_validated() {
+        resources("{}").validate().unwrap();
+        resources(
+            r#"{"memory": "1g", "memory_reservation": "512m", "memory_swap": "2g",
+                "shm_size": "64m", "cpu_shares": 512, "cpu_quota": 50000, "cpu_period": 100000,
+                "cpuset_cpus": "0-1,3", "pids_limit": 128, "blkio_weight": 500}"#,
+        )
+        .validate()
+        .unwrap();
+        resources(r#"{"memory": "1g", "memory_swap": -1, "pids_limit": -1}"#)
+            .validate()
+            .unwrap();
+
+        for (json, error) in [
+            (
+                r#"{"memory": "1m"}"#,
+                "memory 1048576 is less than the minimum of 6m",
+            ),
+            (
+                r#"{"memory": "512m", "memory_reservation": "1g"}"#,
+                "memory_reservation 1073741824 is more than memory 536870912",
+            ),
+            (
+                r#"{"memory": "1g", "memory_swap": "512m"}"#,
+                "memory_swap 536870912 is less than memory 1073741824",
+            ),
+            (
+                r#"{"memory_swap": "1g"}"#,
+                "memory_swap is set without memory",
+            ),
+            (r#"{"shm_size": 0}"#, "shm_size must be positive"),
+            (
+                r#"{"cpu_shares": 1}"#,
+                "cpu_shares 1 is less than the minimum of 2",
+            ),
+            (
+                r#"{"cpu_quota": 999}"#,
+                "cpu_quota 999 is less than the minimum of 1000",
+            ),
+            (
+                r#"{"cpu_period": 2000000}"#,
+                "cpu_period 2000000 is not within 1000 and 1000000",
+            ),
+            (
+                r#"{"cpuset_cpus": "0-"}"#,
+                "invalid cpuset_cpus `0-`, expected e.g. 0-1 or 0,2",
+            ),
+            (
+                r#"{"pids_limit": 0}"#,
+                "pids_limit 0 must be positive or -1",
+            ),
+            (
+                r#"{"blkio_weight": 5}"#,
+                "blkio_weight 5 is not within 10 and 1000",
+            ),
+            (
+                r#"{"memory": "lots"}"#,
+                "Invalid size `lots`, expected e.g. 512m or 1g",
+            ),
+        ] {
+            assert_eq!(invalid(json), error, "{}", json);
+        }
+    }
+
+    #[test]
+    fn resources_exceeding_the_caps() {
+        let caps = resources(
+            r#"{"memory": "1g", "memory_swap": "2g", "shm_size": "64m", "cpu_shares": 1024,
+                "pids_limit": 256, "cpu_quota": 100000, "cpu_period": 100000,
+                "cpuset_cpus": "1-2"}"#,
+        );
+
+        let within = resources(
+            r#"{"memory": "512m", "memory_swap": "1g", "shm_size": "64m", "cpu_shares": 512,
+                "pids_limit": 256, "cpu_quota": 50000, "cpuset_cpus": "2"}"#,
+        );
+        assert!(within.exceeded(&caps).unwrap().is_empty());
+
+        let above = resources(
+            r#"{"memory": "2g", "memory_swap": -1, "shm_size": "128m", "cpu_shares": 2048,
+                "pids_limit": -1, "cpu_quota": 200000, "cpuset_cpus": "0-1"}"#,
+        );
+        assert_eq!(
+            above.exceeded(&caps).unwrap(),
+            [
+                "memory 2147483648 is above 1073741824",
+                "memory_swap is unlimited, the cap is 2147483648",
+                "shm_size 134217728 is above 67108864",
+                "cpu_shares 2048 is above 1024",
+                "pids_limit is unlimited, the cap is 256",
+                "2.00 cpus are above 1.00",
+                "cpuset_cpus 0-1 is not within 1-2",
+            ]
+        );
+
+        assert_eq!(
+            resources("{}").exceeded(&caps).unwrap(),
+            [
+                "memory is unlimited, the cap is 1073741824",
+                "memory_swap is unlimited, the cap is 2147483648",
+                "shm_size is unlimited, the cap is 67108864",
+                "cpu_shares is unlimited, the cap is 1024",
+                "pids_limit is unlimited, the cap is 256",
+                "cpu_quota is unlimited, the cap is 1.00 cpus",
+                "cpuset_cpus is not set, the cap is 1-2",
+            ]
+        );
+
+        // No caps, nothing exceeded.
+        assert!(above.exceeded(&resources("{}")).unwrap().is_empty());
+        assert!(resources(r#"{"memory": "1x"}"#).exceeded(&caps).is_err());
+    }
+
+    #[test]
+    fn resources_or_inherits_unset_limits() {
+        let defaults = resources(
+            r#"{"memory": "512m", "memory_swap": "1g", "memory_reservation": "256m", "pids_limit": 256}"#,
+        );
+
+        assert_eq!(
+            resources(r#"{"pids_limit": 64}"#).or(&defaults),
+            resources(
+                r#"{"memory": "512m", "memory_swap": "1g", "memory_reservation": "256m", "pids_limit": 64}"#
+            )
+        );
+    }
+
+    #[test]
+    fn resources_or_keeps_swap_and_reservation_with_memory() {
+        let defaults = resources(
+            r#"{"memory": "512m", "memory_swap": "1g", "memory_reservation": "256m", "pids_limit": 256}"#,
+        );
+
+        assert_eq!(
+            resources(r#"{"memory": "2g"}"#).or(&defaults),
+            resources(r#"{"memory": "2g", "pids_limit": 256}"#)
+        );
+        assert_eq!(
+            resources(r#"{"memory": "2g", "memory_swap": "4g"}"#).or(&defaults),
+            resources(r#"{"memory": "2g", "memory_swap": "4g", "pids_limit": 256}"#)
+        );
     }
 }
@@ -0,0 +1,281 @@
+#[allow(unused)]
+use {
+    super::arun_config::ArunConfig,
+    arunlib::arun_error::ArunError,
+    base64::{engine::general_purpose::STANDARD, Engine},
+    bollard::auth::DockerCredentials,
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    serde::Deserialize,
+    std::{
+        collections::{BTreeMap, HashMap},
+        env, fs,
+        path::{Path, PathBuf},
+    },
+};
+
+// Credentials from the environment, used for the registry host in ARUN_REGISTRY_HOST,
+// docker.io if it is not set.
+const ENV_HOST: &str = "ARUN_REGISTRY_HOST";
+const ENV_USERNAME: &str = "ARUN_REGISTRY_USERNAME";
+const ENV_PASSWORD: &str = "ARUN_REGISTRY_PASSWORD";
+
+const DOCKER_HUB: &str = "docker.io";
+
+// The auths of a docker config.json, credentials files are in the same format. Debug is
+// not derived so that the secrets are never logged. The keys are sorted so that the same
+// entry is picked whenever several keys name the same registry.
+#[derive(Deserialize, Default)]
+struct AuthFile {
+    #[serde(default)]
+    auths: BTreeMap<String, AuthEntry>,
+    // Credential helpers of docker, they are not run by arun.
+    #[serde(default, rename = "credsStore")]
+    creds_store: Option<String>,
+    #[serde(default, rename = "credHelpers")]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Default)]
+struct AuthEntry {
+    // base64 of username:password
+    auth: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    identitytoken: Option<String>,
+    registrytoken: Option<String>,
+}
+
+// Keys of auths are registry hosts or URLs, e.g. "https://index.docker.io/v1/".
+fn registry_host(key: &str) -> &str {
+    let host = key
+        .split_once("://")
+        .map_or(key, |(_, rest)| rest)
+        .split('/')
+        .next()
+        .unwrap_or_default();
+
+    match host {
+        "index.docker.io" | "registry-1.docker.io" => DOCKER_HUB,
+        h => h,
+    }
+}
+
+impl AuthEntry {
+    // Entries left empty by a credential store of docker have no credentials.
+    fn credentials(
+        self,
+        path: &Path,
+        registry: &str,
+    ) -> Result<Option<DockerCredentials>, ArunError> {
+        let (username, password) = match (self.auth, self.username, self.password) {
+            (Some(auth), None, None) => STANDARD
+                .decode(auth.trim())
+                .ok()
+                .and_then(|d| String::from_utf8(d).ok())
+                .and_then(|d| {
+                    d.split_once(':')
+                        .map(|(u, p)| (Some(u.to_string()), Some(p.to_string())))
+                })
+                .ok_or(ArunError::InvalidValue)
+                .into_report()
+                .attach_printable(format!(
+                    "Invalid auth of {} in {}, expected base64 of username:password",
+                    registry,
+                    path.display()
+                ))?,
+            (_, u, p) => (u, p),
+        };
+
+        if username.is_none() && self.identitytoken.is_none() && self.registrytoken.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(DockerCredentials {
+            username,
+            password,
+            identitytoken: self.identitytoken,
+            registrytoken: self.registrytoken,
+            serveraddress: Some(registry.to_string()),
+            ..Default::default()
+        }))
+    }
+}
+
+// The error of serde_json may quote the content, only the position is reported.
+fn from_file(path: &Path, registry: &str) -> Result<Option<DockerCredentials>, ArunError> {
+    let text = fs::read_to_string(path)
+        .into_report()
+        .change_context(ArunError::IOError)
+        .attach_printable(format!("Failed to read credentials {}", path.display()))?;
+
+    let file: AuthFile = serde_json::from_str(&text).map_err(|e| {
+        Report::new(ArunError::InvalidValue).attach_printable(format!(
+            "Invalid credentials {} at line {} column {}",
+            path.display(),
+            e.line(),
+            e.column()
+        ))
+    })?;
+
+    if let Some(helper) = file.creds_store.as_ref().or_else(|| {
+        file.cred_helpers
+            .iter()
+            .find(|(k, _)| registry_host(k) == registry)
+            .map(|(_, h)| h)
+    }) {
+        jwarn!(
+            "Credential helper {} of {} in {} is not supported, only its auths are used",
+            helper,
+            registry,
+            path.display()
+        );
+    }
+
+    // Keys like "https://index.docker.io/v1/" and "docker.io" name the same registry,
+    // the entries left empty by a credential helper are skipped.
+    for (_, e) in file
+        .auths
+        .into_iter()
+        .filter(|(k, _)| registry_host(k) == registry)
+    {
+        if let Some(c) = e.credentials(path, registry)? {
+            return Ok(Some(c));
+        }
+    }
+
+    Ok(None)
+}
+
+fn from_env(registry: &str) -> Option<DockerCredentials> {
+    let host = env::var(ENV_HOST).unwrap_or_else(|_| DOCKER_HUB.to_string());
+    if registry_host(&host) != registry {
+        return None;
+    }
+
+    Some(DockerCredentials {
+        username: Some(env::var(ENV_USERNAME).ok()?),
+        password: Some(env::var(ENV_PASSWORD).ok()?),
+        serveraddress: Some(registry.to_string()),
+        ..Default::default()
+    })
+}
+
+fn docker_config() -> Option<PathBuf> {
+    let dir = env::var_os("DOCKER_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| Path::new(&h).join(".docker")))?;
+
+    Some(dir.join("config.json")).filter(|p| p.is_file())
+}
+
+// Credentials to pull the image of the app, looked up by the registry host of the image
+// in the credentials file of the app, the environment, then the docker config.json.
+pub fn lookup(config: &ArunConfig) -> Result<Option<DockerCredentials>, ArunError> {
+    let registry = config.image_registry();
+
+    if let Some(path) = config.credentials_file() {
+        if let Some(c) = from_file(Path::new(path), registry)? {
+            jinfo!("Use the credentials of {} from {}", registry, path);
+            return Ok(Some(c));
+        }
+    }
+
+    if let Some(c) = from_env(registry) {
+        jinfo!("Use the credentials of {} from the environment", registry);
+        return Ok(Some(c));
+    }
+
+    if let Some(path) = docker_config() {
+        if let Some(c) = from_file(&path, registry)? {
+            jinfo!(
+                "Use the credentials of {} from {}",
+                registry,
+                path.display()
+            );
+            return Ok(Some(c));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(json: &str) -> AuthEntry {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn credentials(json: &str) -> Result<Option<DockerCredentials>, ArunError> {
+        entry(json).credentials(Path::new("config.json"), "docker.io")
+    }
+
+    #[test]
+    fn registry_host_of_keys() {
+        assert_eq!(registry_host("https://index.docker.io/v1/"), DOCKER_HUB);
+        assert_eq!(registry_host("registry-1.docker.io"), DOCKER_HUB);
+        assert_eq!(registry_host("docker.io"), DOCKER_HUB);
+        assert_eq!(registry_host("ghcr.io"), "ghcr.io");
+        assert_eq!(
+            registry_host("https://registry.local:5000/v2/"),
+            "registry.local:5000"
+        );
+    }
+
+    #[test]
+    fn auth_entry_credentials() {
+        // base64 of joukan:se:cret
+        let c = credentials(r#"{"auth": "am91a2FuOnNlOmNyZXQ="}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(c.username.as_deref(), Some("joukan"));
+        assert_eq!(c.password.as_deref(), Some("se:cret"));
+        assert_eq!(c.serveraddress.as_deref(), Some("docker.io"));
+
+        let c = credentials(r#"{"username": "joukan", "password": "secret"}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(c.username.as_deref(), Some("joukan"));
+        assert_eq!(c.password.as_deref(), Some("secret"));
+
+        let c = credentials(r#"{"identitytoken": "token"}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(c.identitytoken.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn auth_entry_without_credentials() {
+        assert!(credentials("{}").unwrap().is_none());
+        assert!(credentials(r#"{"auth": "not base64"}"#).is_err());
+        // base64 of joukan, without a password
+        assert!(credentials(r#"{"auth": "am91a2Fu"}"#).is_err());
+    }
+
+    #[test]
+    fn from_file_skips_empty_entries() {
+        let path = env::temp_dir().join(format!("arun-credentials-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{
+                "auths": {
+                    "docker.io": {},
+                    "https://index.docker.io/v1/": {"username": "joukan", "password": "secret"},
+                    "ghcr.io": {"username": "other", "password": "other"}
+                },
+                "credsStore": "desktop"
+            }"#,
+        )
+        .unwrap();
+
+        let c = from_file(&path, DOCKER_HUB);
+        let _ = fs::remove_file(&path);
+
+        let c = c.unwrap().unwrap();
+        assert_eq!(c.username.as_deref(), Some("joukan"));
+    }
+}
@@ -1,6 +1,13 @@
 #[allow(unused)]
 use {
-    arunlib::{arun_error::ArunError, utils::IntervalTimer},
+    arunlib::{
+        arun_error::ArunError,
+        ctlif_msg::{
+            ctrl_peer_name, ctrl_socket_path, ArunCtrlCmd, ArunCtrlRequest, ArunCtrlResponse,
+            CTRL_SOCKET_DIR,
+        },
+        utils::IntervalTimer,
+    },
     bollard::{
         container, image,
         models::{DeviceMapping, HostConfig},
@@ -24,7 +31,10 @@ use {
         },
     },
     tokio::{
-        sync::mpsc::{UnboundedReceiver, UnboundedSender},
+        sync::{
+            mpsc::{UnboundedReceiver, UnboundedSender},
+            oneshot,
+        },
         task::{spawn, JoinHandle},
         time::{sleep, timeout, Duration},
     },
@@ -37,34 +47,67 @@ use ipcon_sys::{
     ipcon_async::AsyncIpcon,
     ipcon_msg::IpconMsg,
 };
+#[cfg(not(feature = "ctlif-ipcon"))]
+use {
+    std::fs::{create_dir_all, remove_file},
+    tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixListener,
+    },
+};
+
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
-#[derive(Serialize, Deserialize, Debug)]
-pub enum ArunCtrlCmd {
-    Start,
-    Stop,
-    Remove,
-    Quit,
-    Invalid,
+// A command received from the control interface. Only a request with an id gets a
+// response, a bare ArunCtrlCmd is fire-and-forget.
+#[derive(Debug)]
+pub struct ArunCtrlMsg {
+    pub id: Option<u64>,
+    pub cmd: ArunCtrlCmd,
+    reply: Option<oneshot::Sender<ArunCtrlResponse>>,
 }
 
-impl Display for ArunCtrlCmd {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let msg = match self {
-            ArunCtrlCmd::Start => "ArunCtrCmd::Start",
-            ArunCtrlCmd::Stop => "ArunCtrCmd::Stop",
-            ArunCtrlCmd::Remove => "ArunCtrCmd::Remove",
-            ArunCtrlCmd::Quit => "ArunCtrCmd::Quit",
-            ArunCtrlCmd::Invalid => "ArunCtrlCmd::Invalid",
-        };
-
-        write!(f, "{}", msg)
+impl ArunCtrlMsg {
+    fn parse(body: &str) -> Result<(Self, Option<oneshot::Receiver<ArunCtrlResponse>>), ArunError> {
+        if let Ok(req) = serde_json::from_str::<ArunCtrlRequest>(body) {
+            let (reply, wait_reply) = oneshot::channel();
+
+            return Ok((
+                Self {
+                    id: Some(req.id),
+                    cmd: req.cmd,
+                    reply: Some(reply),
+                },
+                Some(wait_reply),
+            ));
+        }
+
+        let cmd: ArunCtrlCmd = serde_json::from_str(body)
+            .into_report()
+            .change_context(ArunError::InvalidValue)
+            .attach_printable(format!("Failed to parse json command {}", body))?;
+
+        Ok((
+            Self {
+                id: None,
+                cmd,
+                reply: None,
+            },
+            None,
+        ))
+    }
+
+    pub fn reply(self, response: ArunCtrlResponse) {
+        if let Some(reply) = self.reply {
+            // The requester may have gone already.
+            let _ = reply.send(response);
+        }
     }
 }
 
 pub struct ArunCtrl {
     should_quit: Arc<AtomicBool>,
-    stream: UnboundedReceiverStream<ArunCtrlCmd>,
+    stream: UnboundedReceiverStream<ArunCtrlMsg>,
     handler: Option<JoinHandle<Result<(), ArunError>>>,
 }
 
@@ -72,12 +115,12 @@ impl ArunCtrl {
     pub async fn create(name: &str) -> Result<Self, ArunError> {
         #[cfg(feature = "ctlif-ipcon")]
         {
-            let name = format!("arun.{}", name);
+            let name = ctrl_peer_name(name);
 
-            let (sx, rx) = tokio::sync::mpsc::unbounded_channel::<ArunCtrlCmd>();
+            let (sx, rx) = tokio::sync::mpsc::unbounded_channel::<ArunCtrlMsg>();
             let should_quit = Arc::new(AtomicBool::new(false));
 
-            let ih = AsyncIpcon::new(Some(&name), Some(IPF_RCV_IF))
+            let ih = AsyncIpcon::new(Some(&name), Some(IPF_RCV_IF | IPF_SND_IF))
                 .change_context(ArunError::IpconError)?;
 
             let should_quit_in = should_quit.clone();
@@ -93,19 +136,25 @@ impl ArunCtrl {
                                     .trim()
                                     .trim_end_matches('\0');
 
-                                let cmd: ArunCtrlCmd = serde_json::from_str(body)
-                                    .into_report()
-                                    .change_context(ArunError::InvalidValue)
-                                    .attach_printable(format!(
-                                        "Failed to parse json command {}",
-                                        body
-                                    ))?;
+                                let (msg, wait_reply) = ArunCtrlMsg::parse(body)?;
 
-                                jdebug!(from = m.peer, cmd = cmd.to_string());
+                                jdebug!(from = m.peer, cmd = msg.cmd.to_string());
 
-                                sx.send(cmd)
+                                sx.send(msg)
                                     .into_report()
                                     .change_context(ArunError::IOError)?;
+
+                                if let Some(wait_reply) = wait_reply {
+                                    if let Ok(response) = wait_reply.await {
+                                        let buf = serde_json::to_vec(&response)
+                                            .into_report()
+                                            .change_context(ArunError::InvalidValue)?;
+
+                                        if let Err(e) = ih.send_unicast_msg(&m.peer, &buf).await {
+                                            jwarn!("Failed to reply to {}: {:?}", m.peer, e);
+                                        }
+                                    }
+                                }
                             }
                             _ => return Err(ArunError::InvalidValue).into_report(),
                         },
@@ -128,19 +177,105 @@ impl ArunCtrl {
 
         #[cfg(not(feature = "ctlif-ipcon"))]
         {
-            let (sx, rx) = tokio::sync::mpsc::unbounded_channel::<ArunCtrlCmd>();
+            let path = ctrl_socket_path(name);
+
+            create_dir_all(CTRL_SOCKET_DIR)
+                .into_report()
+                .change_context(ArunError::IOError)
+                .attach_printable(format!("Failed to create {}", CTRL_SOCKET_DIR))?;
+
+            // Remove the socket left by a previous instance.
+            if path.exists() {
+                remove_file(&path)
+                    .into_report()
+                    .change_context(ArunError::IOError)
+                    .attach_printable(format!("Failed to remove {}", path.display()))?;
+            }
+
+            let listener = UnixListener::bind(&path)
+                .into_report()
+                .change_context(ArunError::IOError)
+                .attach_printable(format!("Failed to bind {}", path.display()))?;
+
+            let (sx, rx) = tokio::sync::mpsc::unbounded_channel::<ArunCtrlMsg>();
             let should_quit = Arc::new(AtomicBool::new(false));
+
             let should_quit_in = should_quit.clone();
-            let handler = spawn(async move {
+            let handler: JoinHandle<Result<(), ArunError>> = spawn(async move {
                 while !should_quit_in.load(Ordering::Relaxed) {
-                    tokio::time::sleep(Duration::from_secs(u64::MAX)).await;
+                    // This timeout give the chance to check should_quit.
+                    match timeout(Duration::from_secs(3), listener.accept()).await {
+                        // A failed accept, e.g. out of file descriptors, only loses that
+                        // connection, the control interface keeps serving.
+                        Ok(Err(e)) => {
+                            jwarn!("Failed to accept on {}: {}", path.display(), e);
+                            sleep(Duration::from_millis(100)).await;
+                        }
+                        Ok(Ok((stream, _))) => {
+                            let sx = sx.clone();
+
+                            // Every line received on the connection is a json command,
+                            // every response is sent back as one line.
+                            spawn(async move {
+                                let (reader, mut writer) = stream.into_split();
+                                let mut lines = BufReader::new(reader).lines();
+
+                                while let Ok(Some(line)) = lines.next_line().await {
+                                    let body = line.trim();
+                                    if body.is_empty() {
+                                        continue;
+                                    }
+
+                                    let (msg, wait_reply) = match ArunCtrlMsg::parse(body) {
+                                        Ok(m) => m,
+                                        Err(e) => {
+                                            jwarn!("{:?}", e);
+                                            continue;
+                                        }
+                                    };
+
+                                    jdebug!(cmd = msg.cmd.to_string());
+                                    if sx.send(msg).is_err() {
+                                        break;
+                                    }
+
+                                    let response = match wait_reply {
+                                        Some(w) => w.await,
+                                        None => continue,
+                                    };
+
+                                    if let Ok(response) = response {
+                                        let mut buf =
+                                            serde_json::to_vec(&response).unwrap_or_default();
+                                        buf.push(b'\n');
+
+                                        if writer.write_all(&buf).await.is_err() {
+                                            break;
+                                        }
+                                    }
+                                }
+                            });
+                        }
+                        Err(_) => {
+                            jdebug!("Time out");
+                        }
+                    }
                 }
+
+                let _ = remove_file(&path);
+                jdebug!("ArunCtrl thread quit");
+                Ok(())
             });
-            Ok(Self { should_quit, rx })
+
+            Ok(Self {
+                should_quit,
+                stream: UnboundedReceiverStream::from(rx),
+                handler: Some(handler),
+            })
         }
     }
 
-    pub async fn wait_cmd(&mut self) -> Result<ArunCtrlCmd, ArunError> {
+    pub async fn wait_cmd(&mut self) -> Result<ArunCtrlMsg, ArunError> {
         if let Some(cmd) = self.stream.next().await {
             Ok(cmd)
         } else {
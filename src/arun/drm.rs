@@ -0,0 +1,185 @@
+#[allow(unused)]
+use {
+    arunlib::arun_error::ArunError,
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    once_cell::sync::OnceCell,
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+// Where the DRM devices are in the container, and on the host below the root.
+const DRI_DIR: &str = "/dev/dri";
+const SYSFS_DRM_DIR: &str = "sys/class/drm";
+
+static ROOT: OnceCell<PathBuf> = OnceCell::new();
+
+// The host root the sys and dev trees are looked up from, another root allows running
+// against a fake tree. Only the first call has an effect.
+pub fn set_root(root: &str) {
+    let _ = ROOT.set(PathBuf::from(root));
+}
+
+pub fn root() -> &'static Path {
+    ROOT.get().map_or(Path::new("/"), |r| r.as_path())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DrmDevice {
+    pub path: String,
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl DrmDevice {
+    pub fn cgroup_rule(&self) -> String {
+        format!("c {}:{} rwm", self.major, self.minor)
+    }
+}
+
+// Find the DRM nodes of the host from <root>/sys/class/drm. Primary nodes (cardX) are
+// skipped if render_only is set, render nodes (renderDX) are always included. Nodes
+// without a device file in <root>/dev/dri are skipped.
+pub fn discover(root: &Path, render_only: bool) -> Result<Vec<DrmDevice>, ArunError> {
+    let class_dir = root.join(SYSFS_DRM_DIR);
+
+    let entries = match fs::read_dir(&class_dir) {
+        Ok(e) => e,
+        Err(e) => {
+            jwarn!("No DRM device found in {}: {}", class_dir.display(), e);
+            return Ok(vec![]);
+        }
+    };
+
+    let mut devices = vec![];
+    for entry in entries {
+        let entry = entry.into_report().change_context(ArunError::IOError)?;
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        // Connectors like card0-HDMI-A-1 are also listed but have no device node.
+        let is_card = name.starts_with("card") && !name.contains('-');
+        let is_render = name.starts_with("renderD");
+        if !(is_render || is_card && !render_only) {
+            continue;
+        }
+
+        let dev = fs::read_to_string(entry.path().join("dev"))
+            .into_report()
+            .change_context(ArunError::IOError)
+            .attach_printable(format!("Failed to read device number of {}", name))?;
+
+        let (major, minor) = dev
+            .trim()
+            .split_once(':')
+            .and_then(|(ma, mi)| Some((ma.parse::<u32>().ok()?, mi.parse::<u32>().ok()?)))
+            .ok_or(ArunError::InvalidValue)
+            .into_report()
+            .attach_printable(format!("Invalid device number {} of {}", dev.trim(), name))?;
+
+        let path = format!("{}/{}", DRI_DIR, name);
+        let node = root.join(path.trim_start_matches('/'));
+        if !node.exists() {
+            jwarn!(
+                "{} is listed in {} but does not exist",
+                node.display(),
+                class_dir.display()
+            );
+            continue;
+        }
+
+        devices.push(DrmDevice { path, major, minor });
+    }
+
+    devices.sort_by(|a, b| a.path.cmp(&b.path));
+    jdebug!("DRM devices: {:?}", devices);
+
+    Ok(devices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fake root with the sysfs entries and device files of the given nodes, a node
+    // without device number only has a device file.
+    fn fake_root(name: &str, nodes: &[(&str, Option<&str>, bool)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("arun-drm-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        fs::create_dir_all(root.join(DRI_DIR.trim_start_matches('/'))).unwrap();
+        for (node, dev, exists) in nodes {
+            let class = root.join(SYSFS_DRM_DIR).join(node);
+            fs::create_dir_all(&class).unwrap();
+            if let Some(dev) = dev {
+                fs::write(class.join("dev"), format!("{}\n", dev)).unwrap();
+            }
+
+            if *exists {
+                fs::write(root.join("dev/dri").join(node), "").unwrap();
+            }
+        }
+
+        root
+    }
+
+    fn paths(devices: &[DrmDevice]) -> Vec<&str> {
+        devices.iter().map(|d| d.path.as_str()).collect()
+    }
+
+    #[test]
+    fn discover_nodes_of_a_fake_tree() {
+        let root = fake_root(
+            "nodes",
+            &[
+                ("card1", Some("226:1"), true),
+                ("card1-HDMI-A-1", None, false),
+                ("renderD128", Some("226:128"), true),
+                // Listed by sysfs but without a device file.
+                ("card0", Some("226:0"), false),
+                ("version", None, false),
+            ],
+        );
+
+        let all = discover(&root, false);
+        let render = discover(&root, true);
+        let _ = fs::remove_dir_all(&root);
+
+        let all = all.unwrap();
+        assert_eq!(paths(&all), ["/dev/dri/card1", "/dev/dri/renderD128"]);
+        assert_eq!(
+            all.iter().map(|d| d.cgroup_rule()).collect::<Vec<String>>(),
+            ["c 226:1 rwm", "c 226:128 rwm"]
+        );
+
+        let render = render.unwrap();
+        assert_eq!(paths(&render), ["/dev/dri/renderD128"]);
+        assert_eq!((render[0].major, render[0].minor), (226, 128));
+    }
+
+    #[test]
+    fn discover_without_drm() {
+        let root = fake_root("none", &[]);
+        let missing = root.join("missing");
+
+        let empty = discover(&root, false);
+        let none = discover(&missing, false);
+        let _ = fs::remove_dir_all(&root);
+
+        assert!(empty.unwrap().is_empty());
+        assert!(none.unwrap().is_empty());
+    }
+
+    #[test]
+    fn discover_rejects_bad_device_numbers() {
+        let root = fake_root("invalid", &[("renderD128", Some("226"), true)]);
+
+        let result = discover(&root, true);
+        let _ = fs::remove_dir_all(&root);
+
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,163 @@
+#[allow(unused)]
+use {
+    super::arun_config::{HealthCheck, HealthProbe},
+    arunlib::arun_error::ArunError,
+    bollard::{exec, Docker},
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    futures::StreamExt,
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    std::path::Path,
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpStream,
+        time::{timeout, Duration},
+    },
+};
+
+async fn probe_exec(
+    docker: &Docker,
+    container_name: &str,
+    cmd: &[String],
+) -> Result<(), ArunError> {
+    let options = exec::CreateExecOptions {
+        cmd: Some(cmd.to_vec()),
+        attach_stdout: Some(true),
+        attach_stderr: Some(true),
+        ..Default::default()
+    };
+
+    let id = docker
+        .create_exec(container_name, options)
+        .await
+        .into_report()
+        .change_context(ArunError::DockerErr)?
+        .id;
+
+    if let exec::StartExecResults::Attached { mut output, .. } = docker
+        .start_exec(&id, None)
+        .await
+        .into_report()
+        .change_context(ArunError::DockerErr)?
+    {
+        while let Some(o) = output.next().await {
+            let o = o.into_report().change_context(ArunError::DockerErr)?;
+            jtrace!("{}: {}", container_name, o);
+        }
+    }
+
+    let inspect = docker
+        .inspect_exec(&id)
+        .await
+        .into_report()
+        .change_context(ArunError::DockerErr)?;
+
+    match inspect.exit_code {
+        Some(0) => Ok(()),
+        code => Err(ArunError::Unhealthy)
+            .into_report()
+            .attach_printable(format!("{:?} exited with {:?}", cmd, code)),
+    }
+}
+
+async fn probe_tcp(addr: &str) -> Result<(), ArunError> {
+    TcpStream::connect(addr)
+        .await
+        .into_report()
+        .change_context(ArunError::Unhealthy)
+        .attach_printable(format!("Failed to connect to {}", addr))?;
+
+    Ok(())
+}
+
+async fn probe_http(url: &str) -> Result<(), ArunError> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or(ArunError::InvalidValue)
+        .into_report()
+        .attach_printable(format!("Only http:// url is supported: {}", url))?;
+
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+
+    let addr = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = TcpStream::connect(&addr)
+        .await
+        .into_report()
+        .change_context(ArunError::Unhealthy)
+        .attach_printable(format!("Failed to connect to {}", addr))?;
+
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .into_report()
+        .change_context(ArunError::Unhealthy)?;
+
+    // Only the status line is needed, e.g. "HTTP/1.1 200 OK".
+    let mut buf = [0_u8; 64];
+    let n = stream
+        .read(&mut buf)
+        .await
+        .into_report()
+        .change_context(ArunError::Unhealthy)?;
+
+    let status_line = String::from_utf8_lossy(&buf[..n]);
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse::<u16>().ok())
+        .unwrap_or(0);
+
+    if (200..400).contains(&status) {
+        Ok(())
+    } else {
+        Err(ArunError::Unhealthy)
+            .into_report()
+            .attach_printable(format!("GET {} returned {}", url, status_line.trim()))
+    }
+}
+
+// Run the probe of the health check once. An error means the probe failed.
+pub async fn probe(
+    docker: &Docker,
+    container_name: &str,
+    check: &HealthCheck,
+) -> Result<(), ArunError> {
+    let result = timeout(Duration::from_secs(check.timeout() as u64), async {
+        match check.probe() {
+            HealthProbe::Exec(cmd) => probe_exec(docker, container_name, cmd).await,
+            HealthProbe::Tcp(addr) => probe_tcp(addr).await,
+            HealthProbe::Http(url) => probe_http(url).await,
+            HealthProbe::FileExists(path) => {
+                if Path::new(path).exists() {
+                    Ok(())
+                } else {
+                    Err(ArunError::Unhealthy)
+                        .into_report()
+                        .attach_printable(format!("{} does not exist", path))
+                }
+            }
+        }
+    })
+    .await;
+
+    match result {
+        Ok(r) => r,
+        Err(_) => Err(ArunError::Unhealthy)
+            .into_report()
+            .attach_printable(format!("{:?} timed out", check.probe())),
+    }
+}
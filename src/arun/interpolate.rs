@@ -0,0 +1,290 @@
+#[allow(unused)]
+use {
+    super::template,
+    arunlib::arun_error::ArunError,
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    serde_json::{Map, Value},
+    std::{
+        collections::HashMap,
+        env, fs,
+        path::{Path, PathBuf},
+    },
+};
+
+// Files of KEY=VALUE lines providing the variables used in ${VAR}, the host environment
+// takes precedence over them.
+pub const ENV_FILE: &str = "env_file";
+
+// .env files whose entries are added to the environments of the app, the entries listed
+// in environments override them.
+pub const ENVIRONMENT_FILES: &str = "environment_files";
+
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Read a .env file. Empty lines and lines starting with # are skipped, an optional
+// "export " prefix and quotes around the value are removed.
+pub fn parse_env_file(path: &Path) -> Result<Vec<(String, String)>, ArunError> {
+    let text = fs::read_to_string(path)
+        .into_report()
+        .change_context(ArunError::IOError)
+        .attach_printable(format!("Failed to read env file {}", path.display()))?;
+
+    let mut vars = vec![];
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        let (key, value) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v.trim()))
+            .filter(|(k, _)| valid_name(k))
+            .ok_or(ArunError::InvalidValue)
+            .into_report()
+            .attach_printable(format!(
+                "{} line {}: expected KEY=VALUE, found {}",
+                path.display(),
+                n + 1,
+                line
+            ))?;
+
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|q| {
+                value
+                    .strip_prefix(*q)
+                    .and_then(|v| v.strip_suffix(*q))
+                    .filter(|_| value.len() >= 2)
+            })
+            .unwrap_or(value);
+
+        vars.push((key.to_string(), value.to_string()));
+    }
+
+    Ok(vars)
+}
+
+// Replace ${VAR} and ${VAR:-default} in s, the default is used if VAR is unset or empty.
+// $${ is kept as a literal ${.
+fn interpolate_str(s: &str, vars: &HashMap<String, String>) -> Result<String, ArunError> {
+    let mut output = String::new();
+    let mut rest = s;
+
+    while let Some(i) = rest.find('$') {
+        output.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if let Some(r) = rest.strip_prefix("$${") {
+            output.push_str("${");
+            rest = r;
+            continue;
+        }
+
+        let Some(r) = rest.strip_prefix("${") else {
+            output.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+
+        let end = r
+            .find('}')
+            .ok_or(ArunError::InvalidValue)
+            .into_report()
+            .attach_printable(format!("Unterminated ${{ in {}", s))?;
+
+        let expr = &r[..end];
+        let (name, default) = match expr.split_once(":-") {
+            Some((n, d)) => (n, Some(d)),
+            None => (expr, None),
+        };
+
+        if !valid_name(name) {
+            return Err(Report::new(ArunError::InvalidValue)
+                .attach_printable(format!("Invalid variable name {} in {}", name, s)));
+        }
+
+        let value = env::var(name).ok().or_else(|| vars.get(name).cloned());
+        let value = match (value, default) {
+            (Some(v), Some(d)) if v.is_empty() => d.to_string(),
+            (Some(v), _) => v,
+            (None, Some(d)) => d.to_string(),
+            (None, None) => {
+                return Err(Report::new(ArunError::InvalidValue)
+                    .attach_printable(format!("Variable {} used in {} is not set", name, s)))
+            }
+        };
+
+        output.push_str(&value);
+        rest = &r[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+fn interpolate(value: Value, vars: &HashMap<String, String>) -> Result<Value, ArunError> {
+    let value = match value {
+        Value::String(s) => Value::String(interpolate_str(&s, vars)?),
+        Value::Array(a) => Value::Array(
+            a.into_iter()
+                .map(|v| interpolate(v, vars))
+                .collect::<Result<Vec<Value>, ArunError>>()?,
+        ),
+        Value::Object(m) => {
+            let mut map = Map::new();
+            for (k, v) in m {
+                map.insert(k, interpolate(v, vars)?);
+            }
+            Value::Object(map)
+        }
+        v => v,
+    };
+
+    Ok(value)
+}
+
+fn paths(fields: &mut Map<String, Value>, key: &str) -> Result<Vec<String>, ArunError> {
+    match fields.remove(key) {
+        None => Ok(vec![]),
+        Some(Value::String(s)) => Ok(vec![s]),
+        Some(Value::Array(a)) if a.iter().all(|v| v.is_string()) => Ok(a
+            .into_iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect()),
+        Some(v) => Err(
+            Report::new(ArunError::InvalidValue).attach_printable(format!(
+                "Invalid {}: {}, expected a path or a list of paths",
+                key, v
+            )),
+        ),
+    }
+}
+
+// The env files read by an app, relative paths are relative to dir.
+pub fn env_files(value: &Value, dir: &Path) -> Vec<PathBuf> {
+    [ENV_FILE, ENVIRONMENT_FILES]
+        .iter()
+        .filter_map(|k| value.get(k))
+        .flat_map(|v| match v {
+            Value::Array(a) => a.iter().filter_map(|p| p.as_str()).collect(),
+            v => v.as_str().into_iter().collect::<Vec<&str>>(),
+        })
+        .map(|p| dir.join(p))
+        .collect()
+}
+
+// Interpolate the variables in all string fields of the app and add the entries of its
+// environment files. Relative paths are relative to dir, the directory of the config file.
+pub fn resolve(value: Value, dir: &Path) -> Result<Value, ArunError> {
+    let mut fields = match value {
+        Value::Object(m) => m,
+        v => return Ok(v),
+    };
+
+    let mut vars = HashMap::new();
+    for p in paths(&mut fields, ENV_FILE)? {
+        vars.extend(parse_env_file(&dir.join(p))?);
+    }
+
+    let mut fields = fields
+        .into_iter()
+        .map(|(k, v)| Ok((k, interpolate(v, &vars)?)))
+        .collect::<Result<Map<String, Value>, ArunError>>()?;
+
+    let mut environments = vec![];
+    for p in paths(&mut fields, ENVIRONMENT_FILES)? {
+        environments.extend(
+            parse_env_file(&dir.join(p))?
+                .into_iter()
+                .map(|(k, v)| Value::String(format!("{}={}", k, v))),
+        );
+    }
+
+    if !environments.is_empty() {
+        let listed = match fields.remove("environments") {
+            Some(Value::Array(a)) => a,
+            None => vec![],
+            Some(v) => {
+                return Err(Report::new(ArunError::InvalidValue)
+                    .attach_printable(format!("Invalid environments: {}", v)))
+            }
+        };
+
+        fields.insert(
+            "environments".to_string(),
+            Value::Array(template::merge_list("environments", environments, listed)),
+        );
+    }
+
+    Ok(Value::Object(fields))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<String, String> {
+        HashMap::from([
+            ("ARUN_TEST_NAME".to_string(), "app".to_string()),
+            ("ARUN_TEST_EMPTY".to_string(), String::new()),
+        ])
+    }
+
+    #[test]
+    fn interpolate_str_replaces_variables() {
+        let vars = vars();
+
+        assert_eq!(
+            interpolate_str("/data/${ARUN_TEST_NAME}/db", &vars).unwrap(),
+            "/data/app/db"
+        );
+        assert_eq!(
+            interpolate_str("${ARUN_TEST_UNSET:-none}-${ARUN_TEST_EMPTY:-empty}", &vars).unwrap(),
+            "none-empty"
+        );
+        assert_eq!(
+            interpolate_str("${ARUN_TEST_NAME:-other}${ARUN_TEST_EMPTY}", &vars).unwrap(),
+            "app"
+        );
+    }
+
+    #[test]
+    fn interpolate_str_keeps_literals() {
+        let vars = vars();
+
+        assert_eq!(
+            interpolate_str("$${ARUN_TEST_NAME}", &vars).unwrap(),
+            "${ARUN_TEST_NAME}"
+        );
+        assert_eq!(
+            interpolate_str("cost $5 $HOME", &vars).unwrap(),
+            "cost $5 $HOME"
+        );
+        assert_eq!(
+            interpolate_str("no variable", &vars).unwrap(),
+            "no variable"
+        );
+    }
+
+    #[test]
+    fn interpolate_str_errors() {
+        let vars = vars();
+
+        assert!(interpolate_str("${ARUN_TEST_UNSET}", &vars).is_err());
+        assert!(interpolate_str("${ARUN_TEST_NAME", &vars).is_err());
+        assert!(interpolate_str("${1ARUN}", &vars).is_err());
+        assert!(interpolate_str("${}", &vars).is_err());
+    }
+}
@@ -1,3 +1,13 @@
 pub mod arun_config;
+pub mod credentials;
 pub mod ctlif;
+pub mod drm;
+pub mod health;
+pub mod interpolate;
+pub mod platform;
+pub mod policy;
+pub mod registry;
 pub mod runner;
+pub mod supervisor;
+pub mod template;
+pub mod watcher;
@@ -0,0 +1,98 @@
+#[allow(unused)]
+use {
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    once_cell::sync::Lazy,
+    std::{env, fs},
+};
+
+static HOST_PLATFORM: Lazy<String> = Lazy::new(|| {
+    let platform = detect();
+    jdebug!("Host platform {}", platform);
+    platform
+});
+
+// The architecture names used by docker for the rust ones.
+fn docker_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+        "riscv64" => "riscv64",
+        "s390x" => "s390x",
+        a => a,
+    }
+}
+
+// Arm variants are read from the "CPU architecture" of /proc/cpuinfo as containerd does,
+// v8 is the default of arm64 and omitted.
+fn arm_variant(arch: &str) -> Option<String> {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    let version = cpuinfo
+        .lines()
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("CPU architecture"))
+        .map(|(_, v)| v.trim().to_string())?;
+
+    let variant = match (arch, version.as_str()) {
+        ("arm64", "8" | "AArch64") => return None,
+        ("arm", "AArch64") => "v8".to_string(),
+        (_, v) => format!("v{}", v),
+    };
+
+    Some(variant)
+}
+
+fn detect() -> String {
+    let arch = docker_arch(env::consts::ARCH);
+
+    let variant = match arch {
+        "arm" | "arm64" => arm_variant(arch),
+        _ => None,
+    };
+
+    match variant {
+        Some(v) => format!("{}/{}/{}", env::consts::OS, arch, v),
+        None => format!("{}/{}", env::consts::OS, arch),
+    }
+}
+
+// os/arch[/variant] of the host as docker names it, e.g. linux/amd64 or linux/arm/v7.
+pub fn host_platform() -> &'static str {
+    HOST_PLATFORM.as_str()
+}
+
+// A platform is os/arch with an optional variant.
+pub fn valid(platform: &str) -> bool {
+    let parts: Vec<&str> = platform.split('/').collect();
+    (2..=3).contains(&parts.len()) && parts.iter().all(|p| !p.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn docker_arch_names() {
+        assert_eq!(docker_arch("x86_64"), "amd64");
+        assert_eq!(docker_arch("x86"), "386");
+        assert_eq!(docker_arch("aarch64"), "arm64");
+        assert_eq!(docker_arch("arm"), "arm");
+        assert_eq!(docker_arch("riscv64"), "riscv64");
+    }
+
+    #[test]
+    fn valid_platforms() {
+        assert!(valid("linux/amd64"));
+        assert!(valid("linux/arm/v7"));
+        assert!(valid(host_platform()));
+
+        assert!(!valid("linux"));
+        assert!(!valid("linux/"));
+        assert!(!valid("/amd64"));
+        assert!(!valid("linux//v7"));
+        assert!(!valid("linux/arm/v7/extra"));
+    }
+}
@@ -0,0 +1,477 @@
+#[allow(unused)]
+use {
+    super::arun_config::{AppType, ArunConfig, ConfigFormat, Resources},
+    arunlib::arun_error::ArunError,
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    regex::Regex,
+    serde::{Deserialize, Serialize},
+    std::{
+        fs,
+        path::{Component, Path},
+    },
+};
+
+// Restrictions on what User apps may request. A rule not set does not restrict anything,
+// Sys apps are not restricted.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_privilege: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_host_network: Option<bool>,
+    // Mapping host devices explicitly, the DRM devices of the gui and gpu-render features
+    // are ruled by allow_gpu.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_devices: Option<bool>,
+    // The DRM devices mapped for the gui and gpu-render features.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_gpu: Option<bool>,
+    // Turning off seccomp, AppArmor or the masking of the system paths with
+    // "<option>=unconfined" in security_opt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_unconfined: Option<bool>,
+    // Sharing the user namespace of the host with userns_mode "host".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allow_host_userns: Option<bool>,
+    // Host paths under which binds are allowed, named volumes are always allowed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_bind_prefixes: Option<Vec<String>>,
+    // Image names, * matches any characters, e.g. "joukan/*". With a tag, e.g.
+    // "joukan/rpi4-appimg:0.1.*", the version is matched too.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_images: Option<Vec<String>>,
+    // Registry hosts, docker.io for images without one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_registries: Option<Vec<String>>,
+    // Capabilities which may be added by cap_add.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_capabilities: Option<Vec<String>>,
+    // Highest limits, a User app has to set every capped limit, e.g. from the resource
+    // defaults.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_resources: Option<Resources>,
+}
+
+const UNCONFINED_OPTIONS: [&str; 3] = ["seccomp", "apparmor", "systempaths"];
+
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let re = format!("^{}$", regex::escape(pattern).replace(r"\*", ".*"));
+    Regex::new(&re).is_ok_and(|r| r.is_match(value))
+}
+
+// The confinement turned off by a security option, e.g. seccomp for "seccomp=unconfined".
+fn unconfined(opt: &str) -> Option<&str> {
+    let (name, value) = opt.split_once(['=', ':'])?;
+
+    (value == "unconfined" && UNCONFINED_OPTIONS.contains(&name)).then_some(name)
+}
+
+fn capability_name(cap: &str) -> String {
+    let cap = cap.to_uppercase();
+    cap.strip_prefix("CAP_").unwrap_or(&cap).to_string()
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self, ArunError> {
+        let text = fs::read_to_string(path)
+            .into_report()
+            .change_context(ArunError::IOError)
+            .attach_printable(format!("Failed to read {}", path.display()))?;
+
+        let policy: Policy = ConfigFormat::detect(path, &text)
+            .deserialize(&text)
+            .attach_printable(format!("Invalid policy {}", path.display()))?;
+
+        if let Some(r) = &policy.max_resources {
+            r.validate()
+                .attach_printable(format!("Invalid max_resources in {}", path.display()))?;
+        }
+
+        Ok(policy)
+    }
+
+    // The rules broken by the config, each described with the value breaking it.
+    pub fn violations(&self, config: &ArunConfig) -> Result<Vec<String>, ArunError> {
+        let mut violations = vec![];
+
+        if config.app_type() != AppType::User {
+            return Ok(violations);
+        }
+
+        if self.allow_privilege == Some(false) && config.privilege() {
+            violations.push("allow_privilege: privileged apps are not allowed".to_string());
+        }
+
+        if self.allow_host_network == Some(false) && config.network() == "host" {
+            violations.push("allow_host_network: host network is not allowed".to_string());
+        }
+
+        if self.allow_devices == Some(false)
+            && (!config.devices().is_empty() || !config.device_cgroup_rules().is_empty())
+        {
+            violations.push("allow_devices: mapping host devices is not allowed".to_string());
+        }
+
+        if self.allow_gpu == Some(false) && !config.privilege() {
+            for (f, used) in [("gui", config.gui()), ("gpu-render", config.gpu_render())] {
+                if used {
+                    violations.push(format!(
+                        "allow_gpu: the DRM devices of the {} feature are not allowed",
+                        f
+                    ));
+                }
+            }
+        }
+
+        if self.allow_unconfined == Some(false) {
+            for o in config.security_opt() {
+                if let Some(name) = unconfined(o) {
+                    violations.push(format!(
+                        "allow_unconfined: security option {} turning off {} is not allowed",
+                        o, name
+                    ));
+                }
+            }
+        }
+
+        if self.allow_host_userns == Some(false) && config.userns_mode() == Some("host") {
+            violations.push("allow_host_userns: host user namespace is not allowed".to_string());
+        }
+
+        if let Some(prefixes) = &self.allowed_bind_prefixes {
+            for b in config.binds() {
+                let src = b.split(':').next().unwrap_or(b);
+                if !src.starts_with('/') {
+                    continue;
+                }
+
+                let path = Path::new(src);
+                let allowed = !path.components().any(|c| c == Component::ParentDir)
+                    && prefixes.iter().any(|p| path.starts_with(p));
+
+                if !allowed {
+                    violations.push(format!(
+                        "allowed_bind_prefixes: bind source {} is not under {}",
+                        src,
+                        prefixes.join(", ")
+                    ));
+                }
+            }
+        }
+
+        if let Some(images) = &self.allowed_images {
+            let allowed = images.iter().any(|p| {
+                if p.rsplit('/').next().is_some_and(|l| l.contains(':')) {
+                    glob_match(p, &config.image())
+                } else {
+                    glob_match(p, config.image_name())
+                }
+            });
+
+            if !allowed {
+                violations.push(format!(
+                    "allowed_images: image {} is not one of {}",
+                    config.image(),
+                    images.join(", ")
+                ));
+            }
+        }
+
+        if let Some(registries) = &self.allowed_registries {
+            if !registries.iter().any(|r| r == config.image_registry()) {
+                violations.push(format!(
+                    "allowed_registries: registry {} is not one of {}",
+                    config.image_registry(),
+                    registries.join(", ")
+                ));
+            }
+        }
+
+        if let Some(caps) = &self.allowed_capabilities {
+            let allowed: Vec<String> = caps.iter().map(|c| capability_name(c)).collect();
+            for c in config.cap_add() {
+                if !allowed.contains(&capability_name(c)) {
+                    violations.push(format!(
+                        "allowed_capabilities: capability {} is not allowed",
+                        c
+                    ));
+                }
+            }
+        }
+
+        if let Some(caps) = &self.max_resources {
+            for e in config.resources().exceeded(caps)? {
+                violations.push(format!("max_resources: {}", e));
+            }
+        }
+
+        Ok(violations)
+    }
+
+    pub fn check(&self, config: &ArunConfig) -> Result<(), ArunError> {
+        let violations = self.violations(config)?;
+        if violations.is_empty() {
+            return Ok(());
+        }
+
+        // Attached last to first, so that they are printed in the order of the rules.
+        let mut report = Report::new(ArunError::PolicyViolation);
+        for v in violations.into_iter().rev() {
+            report = report.attach_printable(v);
+        }
+
+        Err(report.attach_printable(format!("{} is refused by the policy", config.appid())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn app(app_type: &str, extra: serde_json::Value) -> ArunConfig {
+        let mut value = json!({
+            "name": "app",
+            "app_type": app_type,
+            "image": "joukan/app",
+            "version": "0.1",
+            "privilege": false,
+            "network": "none",
+            "cmd": ["/bin/app"],
+            "binds": [],
+            "features": [],
+            "environments": []
+        });
+        if let (Some(v), serde_json::Value::Object(extra)) = (value.as_object_mut(), extra) {
+            v.extend(extra);
+        }
+
+        serde_json::from_value(value).unwrap()
+    }
+
+    // Each rule with the settings it allows and refuses, and the violations reported.
+    fn check_rule(policy: serde_json::Value, cases: &[(serde_json::Value, &[&str])]) {
+        let policy: Policy = serde_json::from_value(policy).unwrap();
+
+        for (extra, expected) in cases {
+            let config = app("User", extra.clone());
+            assert_eq!(&policy.violations(&config).unwrap(), expected, "{}", extra);
+
+            // Sys apps are not restricted.
+            let config = app("Sys", extra.clone());
+            assert!(policy.violations(&config).unwrap().is_empty(), "{}", extra);
+        }
+    }
+
+    #[test]
+    fn privilege() {
+        check_rule(
+            json!({"allow_privilege": false}),
+            &[
+                (json!({}), &[]),
+                (
+                    json!({"privilege": true}),
+                    &["allow_privilege: privileged apps are not allowed"],
+                ),
+            ],
+        );
+        check_rule(
+            json!({"allow_privilege": true}),
+            &[(json!({"privilege": true}), &[])],
+        );
+    }
+
+    #[test]
+    fn host_network() {
+        check_rule(
+            json!({"allow_host_network": false}),
+            &[
+                (json!({"network": "bridge"}), &[]),
+                (
+                    json!({"network": "host"}),
+                    &["allow_host_network: host network is not allowed"],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn devices() {
+        check_rule(
+            json!({"allow_devices": false, "allow_gpu": false}),
+            &[
+                (json!({}), &[]),
+                (
+                    json!({"devices": [{"path_on_host": "/dev/ttyUSB0"}]}),
+                    &["allow_devices: mapping host devices is not allowed"],
+                ),
+                (
+                    json!({"device_cgroup_rules": ["c 188:* rwm"]}),
+                    &["allow_devices: mapping host devices is not allowed"],
+                ),
+                (
+                    json!({"features": ["gui", "gpu-render"]}),
+                    &[
+                        "allow_gpu: the DRM devices of the gui feature are not allowed",
+                        "allow_gpu: the DRM devices of the gpu-render feature are not allowed",
+                    ],
+                ),
+                (json!({"features": ["wayland"]}), &[]),
+            ],
+        );
+    }
+
+    #[test]
+    fn security_options() {
+        check_rule(
+            json!({"allow_unconfined": false, "allow_host_userns": false}),
+            &[
+                (
+                    json!({"security_opt": ["no-new-privileges", "apparmor=docker-default"]}),
+                    &[],
+                ),
+                (
+                    json!({"security_opt": ["seccomp=unconfined", "systempaths=unconfined"]}),
+                    &[
+                        "allow_unconfined: security option seccomp=unconfined turning off seccomp is not allowed",
+                        "allow_unconfined: security option systempaths=unconfined turning off systempaths is not allowed",
+                    ],
+                ),
+                (
+                    json!({"userns_mode": "host"}),
+                    &["allow_host_userns: host user namespace is not allowed"],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn bind_prefixes() {
+        check_rule(
+            json!({"allowed_bind_prefixes": ["/srv/apps", "/tmp"]}),
+            &[
+                (
+                    json!({"binds": ["/srv/apps/a:/data", "/tmp:/tmp:ro", "appdata:/var/lib/app"]}),
+                    &[],
+                ),
+                (
+                    json!({"binds": ["/srv/apps-other:/data"]}),
+                    &["allowed_bind_prefixes: bind source /srv/apps-other is not under /srv/apps, /tmp"],
+                ),
+                (
+                    json!({"binds": ["/srv/apps/../../etc:/etc"]}),
+                    &["allowed_bind_prefixes: bind source /srv/apps/../../etc is not under /srv/apps, /tmp"],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn images_and_registries() {
+        check_rule(
+            json!({"allowed_images": ["joukan/*", "registry.local:5000/hmi/app:0.1.*"]}),
+            &[
+                (json!({"image": "joukan/rpi4-appimg"}), &[]),
+                (json!({"image": "registry.local:5000/hmi/app", "version": "0.1.3"}), &[]),
+                (
+                    json!({"image": "registry.local:5000/hmi/app", "version": "0.2.0"}),
+                    &["allowed_images: image registry.local:5000/hmi/app:0.2.0 is not one of joukan/*, registry.local:5000/hmi/app:0.1.*"],
+                ),
+                (
+                    json!({"image": "evil/joukan"}),
+                    &["allowed_images: image evil/joukan:0.1 is not one of joukan/*, registry.local:5000/hmi/app:0.1.*"],
+                ),
+            ],
+        );
+        check_rule(
+            json!({"allowed_registries": ["docker.io", "registry.local:5000"]}),
+            &[
+                (json!({"image": "joukan/app"}), &[]),
+                (json!({"image": "registry.local:5000/hmi/app"}), &[]),
+                (
+                    json!({"image": "ghcr.io/joukan/app"}),
+                    &["allowed_registries: registry ghcr.io is not one of docker.io, registry.local:5000"],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn capabilities() {
+        check_rule(
+            json!({"allowed_capabilities": ["NET_BIND_SERVICE", "cap_sys_nice"]}),
+            &[
+                (
+                    json!({"cap_add": ["CAP_NET_BIND_SERVICE", "sys_nice"]}),
+                    &[],
+                ),
+                (json!({"cap_drop": ["ALL"]}), &[]),
+                (
+                    json!({"cap_add": ["NET_ADMIN", "SYS_NICE"]}),
+                    &["allowed_capabilities: capability NET_ADMIN is not allowed"],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn resource_caps() {
+        check_rule(
+            json!({"max_resources": {"memory": "512m", "pids_limit": 128}}),
+            &[
+                (
+                    json!({"resources": {"memory": "256m", "pids_limit": 64}}),
+                    &[],
+                ),
+                (
+                    json!({"resources": {"memory": "1g"}}),
+                    &[
+                        "max_resources: memory 1073741824 is above 536870912",
+                        "max_resources: pids_limit is unlimited, the cap is 128",
+                    ],
+                ),
+            ],
+        );
+    }
+
+    #[test]
+    fn no_rules_allow_everything() {
+        check_rule(
+            json!({}),
+            &[(
+                json!({"privilege": true, "network": "host", "userns_mode": "host",
+                       "security_opt": ["seccomp=unconfined"], "cap_add": ["ALL"]}),
+                &[],
+            )],
+        );
+    }
+
+    #[test]
+    fn check_reports_every_violation() {
+        let policy: Policy =
+            serde_json::from_value(json!({"allow_privilege": false, "allow_host_network": false}))
+                .unwrap();
+        let config = app("User", json!({"privilege": true, "network": "host"}));
+
+        let e = policy.check(&config).unwrap_err();
+        assert!(matches!(e.current_context(), ArunError::PolicyViolation));
+
+        let messages: Vec<String> = e
+            .frames()
+            .filter_map(|f| f.downcast_ref::<String>().cloned())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "user.app is refused by the policy",
+                "allow_privilege: privileged apps are not allowed",
+                "allow_host_network: host network is not allowed",
+            ]
+        );
+
+        assert!(policy.check(&app("User", json!({}))).is_ok());
+    }
+}
@@ -0,0 +1,99 @@
+#[allow(unused)]
+use {
+    arunlib::ctlif_msg::{RunnerHealth, RunnerState},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    std::{
+        collections::HashMap,
+        sync::{Arc, RwLock},
+    },
+    tokio::sync::watch,
+};
+
+struct AppEntry {
+    state: RunnerState,
+    health: RunnerHealth,
+    depends_on: Vec<String>,
+}
+
+// State of all apps supervised by one arun process, shared by their runners so that an
+// app can wait for the apps it depends on and, on shutdown, for the apps depending on it.
+#[derive(Clone)]
+pub struct AppRegistry {
+    apps: Arc<RwLock<HashMap<String, AppEntry>>>,
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+impl Default for AppRegistry {
+    fn default() -> Self {
+        let (shutdown, _) = watch::channel(false);
+
+        Self {
+            apps: Arc::new(RwLock::new(HashMap::new())),
+            shutdown: Arc::new(shutdown),
+        }
+    }
+}
+
+impl AppRegistry {
+    pub fn register(&self, appid: &str, depends_on: Vec<String>) {
+        self.apps.write().unwrap().insert(
+            appid.to_string(),
+            AppEntry {
+                state: RunnerState::NonExist,
+                health: RunnerHealth::Unknown,
+                depends_on,
+            },
+        );
+    }
+
+    pub fn update_depends_on(&self, appid: &str, depends_on: Vec<String>) {
+        if let Some(entry) = self.apps.write().unwrap().get_mut(appid) {
+            entry.depends_on = depends_on;
+        }
+    }
+
+    pub fn unregister(&self, appid: &str) {
+        self.apps.write().unwrap().remove(appid);
+    }
+
+    pub fn update_state(&self, appid: &str, state: RunnerState) {
+        if let Some(entry) = self.apps.write().unwrap().get_mut(appid) {
+            entry.state = state;
+        }
+    }
+
+    pub fn update_health(&self, appid: &str, health: RunnerHealth) {
+        if let Some(entry) = self.apps.write().unwrap().get_mut(appid) {
+            entry.health = health;
+        }
+    }
+
+    pub fn state(&self, appid: &str) -> Option<RunnerState> {
+        self.apps.read().unwrap().get(appid).map(|e| e.state)
+    }
+
+    pub fn health(&self, appid: &str) -> Option<RunnerHealth> {
+        self.apps.read().unwrap().get(appid).map(|e| e.health)
+    }
+
+    // Apps which depend on appid, together with their current state.
+    pub fn dependents(&self, appid: &str) -> Vec<(String, RunnerState)> {
+        self.apps
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, e)| e.depends_on.iter().any(|d| d == appid))
+            .map(|(id, e)| (id.clone(), e.state))
+            .collect()
+    }
+
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn subscribe_shutdown(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+}
@@ -1,33 +1,128 @@
 #[allow(unused)]
 use {
     super::{
-        arun_config::ArunConfig,
-        ctlif::{ArunCtrl, ArunCtrlCmd},
+        arun_config::{self, ArunConfig, ConfigChange, DependsOn, DriftAction, RestartMode},
+        credentials,
+        ctlif::ArunCtrl,
+        drm, health,
+        policy::Policy,
+        registry::AppRegistry,
+    },
+    arunlib::{
+        arun_error::ArunError,
+        ctlif_msg::{ArunCtrlCmd, ArunCtrlResponse, RunnerHealth, RunnerState},
+        utils::IntervalTimer,
     },
-    arunlib::{arun_error::ArunError, utils::IntervalTimer},
     bollard::{
         container, image,
         models::{
-            CreateImageInfo, DeviceMapping, EndpointIpamConfig, EndpointSettings, HostConfig, Ipam,
-            IpamConfig, PortBinding,
+            ContainerSummary, CreateImageInfo, DeviceMapping, EndpointIpamConfig, EndpointSettings,
+            EventMessage, HostConfig, Ipam, IpamConfig, PortBinding,
         },
-        network, Docker,
+        network, system, Docker,
     },
     error_stack::{IntoReport, Report, Result, ResultExt},
-    futures::StreamExt,
+    futures::{Stream, StreamExt},
     jlogger_tracing::{
         jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
     },
     regex::Regex,
     serde::{Deserialize, Serialize},
     serde_json,
-    std::{collections::HashMap, fmt::Display, str::FromStr},
-    tokio::sync::mpsc,
+    std::{
+        collections::HashMap, fmt::Display, fs, os::unix::fs::FileTypeExt, path::Path, pin::Pin,
+        str::FromStr,
+    },
+    tokio::{
+        sync::{mpsc, oneshot},
+        time::{sleep, Duration, Instant},
+    },
+    tokio_util::io::ReaderStream,
 };
 
-const DEFAULT_NETWORK_SUBNET: &'static str = "192.168.10.0/24";
-const DEFAULT_NETWORK_NAME: &'static str = "virt-network0";
-const REDIS_SERVER_IP: &'static str = "192.168.10.10";
+const DEFAULT_NETWORK_SUBNET: &str = "192.168.10.0/24";
+const DEFAULT_NETWORK_NAME: &str = "virt-network0";
+const REDIS_SERVER_IP: &str = "192.168.10.10";
+
+// Labels stamped on the containers created by arun.
+const LABEL_APPID: &str = "arun.appid";
+const LABEL_CONFIG_HASH: &str = "arun.config-hash";
+const LABEL_VERSION: &str = "arun.version";
+
+// Container state is tracked through docker events, polling is only a safety net for
+// missed events.
+const STATE_POLL_INTERVAL_S: u32 = 30;
+
+// Delays between the attempts to resync after the docker events stream broke, e.g. while
+// the daemon restarts.
+const RESYNC_DELAY_MIN_S: u64 = 1;
+const RESYNC_DELAY_MAX_S: u64 = 30;
+
+type EventStream =
+    Pin<Box<dyn Stream<Item = std::result::Result<EventMessage, bollard::errors::Error>> + Send>>;
+
+// Schedule of the resyncs after the docker events stream broke. The delay is doubled on
+// every failed attempt up to RESYNC_DELAY_MAX_S.
+#[derive(Debug)]
+struct Resync {
+    delay: Duration,
+    at: Option<Instant>,
+}
+
+impl Default for Resync {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_secs(RESYNC_DELAY_MIN_S),
+            at: None,
+        }
+    }
+}
+
+impl Resync {
+    fn broken(&mut self) {
+        if self.at.is_none() {
+            self.at = Some(Instant::now() + self.delay);
+        }
+    }
+
+    fn pending(&self) -> bool {
+        self.at.is_some()
+    }
+
+    async fn wait(&self) {
+        if let Some(at) = self.at {
+            tokio::time::sleep_until(at).await;
+        }
+    }
+
+    // Record the result of an attempt, returns true once resynced.
+    fn synced(&mut self, result: Result<(), ArunError>) -> bool {
+        match result {
+            Ok(()) => {
+                *self = Resync::default();
+                true
+            }
+            Err(e) => {
+                self.delay = (self.delay * 2).min(Duration::from_secs(RESYNC_DELAY_MAX_S));
+                self.at = Some(Instant::now() + self.delay);
+                jwarn!("Failed to resync, retry in {:?}: {:?}", self.delay, e);
+                false
+            }
+        }
+    }
+}
+
+// Errors talking to docker, e.g. while the daemon restarts, are logged and retried later
+// rather than ending the runner.
+fn transient<T: Default>(result: Result<T, ArunError>) -> Result<T, ArunError> {
+    match result {
+        Err(e) if matches!(e.current_context(), ArunError::DockerErr) => {
+            jwarn!("Docker request failed, retry later: {:?}", e);
+            Ok(T::default())
+        }
+        r => r,
+    }
+}
 
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 enum RunnerRequest {
@@ -44,56 +139,52 @@ impl Display for RunnerRequest {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
-pub enum RunnerState {
-    NonExist,
-    Created,
-    Running,
-    Restarting,
-    Exited,
-    Paused,
-    Dead,
+// What a container has been created from, read back from its image and labels.
+#[derive(PartialEq, Eq, Debug, Clone)]
+struct ContainerSpec {
+    image: String,
+    // None for containers created before the labels were stamped.
+    config_hash: Option<String>,
 }
 
-impl Display for RunnerState {
+impl Display for ContainerSpec {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let state_str = match self {
-            RunnerState::NonExist => "NonExist",
-            RunnerState::Created => "Created",
-            RunnerState::Running => "Running",
-            RunnerState::Restarting => "Restarting",
-            RunnerState::Exited => "Exited",
-            RunnerState::Dead => "Dead",
-            RunnerState::Paused => "Paused",
-        };
-
-        write!(f, "{}", state_str)
+        write!(
+            f,
+            "image {} config {}",
+            self.image,
+            self.config_hash.as_deref().unwrap_or("unknown")
+        )
     }
 }
 
-impl FromStr for RunnerState {
-    type Err = ArunError;
-
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s {
-            "created" => Ok(RunnerState::Created),
-            "running" => Ok(RunnerState::Running),
-            "restarting" => Ok(RunnerState::Restarting),
-            "exited" => Ok(RunnerState::Exited),
-            "paused" => Ok(RunnerState::Paused),
-            "dead" => Ok(RunnerState::Dead),
-            "nonExist" => Ok(RunnerState::NonExist),
-            _ => Err(ArunError::Unknown),
-        }
-    }
+// Changes of the config file applied by the supervisor to a running app.
+#[derive(Debug)]
+pub enum RunnerUpdate {
+    // Replied with whether the app runs with the new config.
+    Config(Box<ArunConfig>, oneshot::Sender<bool>),
+    // The app has been removed from the config.
+    Remove,
 }
 
 pub struct Runner {
     state: RunnerState,
+    health: RunnerHealth,
+    health_failures: u32,
     target_state: RunnerState,
     docker: Docker,
     config: ArunConfig,
     network_id: String,
+    registry: AppRegistry,
+    policy: Policy,
+    shutting_down: bool,
+    started_at: Option<Instant>,
+    stopped_unhealthy: bool,
+    // Spec of the existing container if it differs from the config.
+    drift: Option<ContainerSpec>,
+    failures: u32,
+    restart_at: Option<Instant>,
+    quarantined: bool,
 }
 
 impl Runner {
@@ -123,46 +214,227 @@ impl Runner {
         Ok(found)
     }
 
-    pub async fn install(&self) -> Result<(), ArunError> {
+    // Load the image from a docker save tarball, the docker daemon decompresses it.
+    async fn load_archive(&self, archive: &str) -> Result<(), ArunError> {
+        let file = tokio::fs::File::open(archive)
+            .await
+            .into_report()
+            .change_context(ArunError::IOError)
+            .attach_printable(format!("Failed to open image archive {}", archive))?;
+
+        let body = hyper::Body::wrap_stream(ReaderStream::new(file));
+        let options = image::ImportImageOptions { quiet: true };
+        let mut stream = self.docker.import_image(options, body, None);
+
+        while let Some(item) = stream.next().await {
+            let info = item
+                .into_report()
+                .change_context(ArunError::DockerErr)
+                .attach_printable(format!("Failed to load image archive {}", archive))?;
+
+            if let Some(s) = info.stream {
+                jinfo!("{}", s.trim());
+            }
+        }
+
+        if !self.find_image().await? {
+            return Err(ArunError::InvalidValue)
+                .into_report()
+                .attach_printable(format!(
+                    "Image archive {} does not contain {}",
+                    archive,
+                    self.config.image()
+                ));
+        }
+
+        Ok(())
+    }
+
+    // Pull the image by a tag or a digest.
+    async fn pull(&self, reference: &str) -> Result<(), ArunError> {
         let options = image::CreateImageOptions::<String> {
             from_image: self.config.image_name().to_string(),
-            tag: self.config.image_version().to_string(),
-            platform: "linux/arm64".to_string(),
+            tag: reference.to_string(),
+            platform: self.config.platform().to_string(),
             ..Default::default()
         };
 
-        let mut stream = self.docker.create_image(Some(options), None, None);
+        let credentials = credentials::lookup(&self.config)?;
+        let mut stream = self.docker.create_image(Some(options), None, credentials);
 
         while let Some(item) = stream.next().await {
-            let info = item.into_report().change_context(ArunError::DockerErr)?;
+            let info = item
+                .into_report()
+                .change_context(ArunError::DockerErr)
+                .attach_printable(format!(
+                    "Failed to pull {}:{}",
+                    self.config.image_name(),
+                    reference
+                ))?;
             jinfo!("{:?}", info);
         }
 
         Ok(())
     }
 
+    pub async fn install(&self) -> Result<(), ArunError> {
+        if let Some(archive) = self.config.image_archive() {
+            return self.load_archive(archive).await;
+        }
+
+        // A pinned image is pulled by digest rather than by a tag which may have moved,
+        // then tagged with its version for the container to be created from. A digest
+        // which is the image ID is not known by the registry, the version is pulled and
+        // verified against it instead.
+        if let Some(digest) = self.config.digest() {
+            let name = self.config.image_name();
+
+            match self.pull(digest).await {
+                Ok(()) => {
+                    let options = image::TagImageOptions {
+                        repo: name,
+                        tag: self.config.image_version(),
+                    };
+
+                    return self
+                        .docker
+                        .tag_image(&format!("{}@{}", name, digest), Some(options))
+                        .await
+                        .into_report()
+                        .change_context(ArunError::DockerErr)
+                        .attach_printable(format!(
+                            "Failed to tag {}@{} as {}",
+                            name,
+                            digest,
+                            self.config.image()
+                        ));
+                }
+                Err(e) => jinfo!(
+                    "Failed to pull {}@{}, pull {} instead: {:?}",
+                    name,
+                    digest,
+                    self.config.image(),
+                    e
+                ),
+            }
+        }
+
+        self.pull(self.config.image_version()).await
+    }
+
+    // An image pinned by digest has to be the pinned one, by its ID or by the digest of a
+    // manifest it was pulled or pushed with.
+    async fn verify_digest(&self) -> Result<(), ArunError> {
+        let Some(digest) = self.config.digest() else {
+            return Ok(());
+        };
+
+        let image = self.config.image();
+        let inspect = self
+            .docker
+            .inspect_image(&image)
+            .await
+            .into_report()
+            .change_context(ArunError::DockerErr)
+            .attach_printable(format!("Failed to inspect image {}", image))?;
+
+        let id = inspect.id.unwrap_or_default();
+        let repo_digests = inspect.repo_digests.unwrap_or_default();
+
+        if id == digest
+            || repo_digests
+                .iter()
+                .any(|d| d.rsplit_once('@').is_some_and(|(_, d)| d == digest))
+        {
+            return Ok(());
+        }
+
+        Err(ArunError::DigestMismatch)
+            .into_report()
+            .attach_printable(format!(
+                "Image {} is {} with digests [{}], pinned to {}",
+                image,
+                id,
+                repo_digests.join(", "),
+                digest
+            ))
+    }
+
+    // Install the image if it is not present or is not the pinned one, then check it
+    // against the pinned digest.
+    async fn prepare_image(&self) -> Result<(), ArunError> {
+        if !self.find_image().await? {
+            jinfo!("Install image {}", self.config.image());
+            self.install().await?;
+        } else if self.config.digest().is_some() && self.verify_digest().await.is_err() {
+            jinfo!(
+                "Image {} is not the pinned one, install it again",
+                self.config.image()
+            );
+            self.install().await?;
+        }
+
+        self.verify_digest().await
+    }
+
+    // The docker API takes the content of a seccomp profile rather than its path as the
+    // docker cli does.
+    fn security_opt(arun_config: &ArunConfig) -> Result<Vec<String>, ArunError> {
+        let mut options = vec![];
+
+        for o in arun_config.security_opt() {
+            match arun_config::seccomp_profile(o) {
+                Some(path) => {
+                    let content = fs::read_to_string(path)
+                        .into_report()
+                        .change_context(ArunError::IOError)
+                        .attach_printable(format!("Failed to read seccomp profile {}", path))?;
+                    options.push(format!("seccomp={}", content));
+                }
+                None => options.push(o.clone()),
+            }
+        }
+
+        Ok(options)
+    }
+
     pub fn host_config(arun_config: &ArunConfig) -> Result<HostConfig, ArunError> {
-        let mut device_mapping = vec![];
-        let mut cgroup_rules: Vec<String> = vec![];
+        let mut device_mapping: Vec<DeviceMapping> = arun_config
+            .devices()
+            .iter()
+            .map(DeviceMapping::from)
+            .collect();
+        let mut cgroup_rules: Vec<String> = arun_config.device_cgroup_rules().to_vec();
 
         let mut binds: Vec<String> = arun_config.binds().iter().map(|s| s.to_string()).collect();
 
         // A privileged container dose not need a specific device mapping.
-        // 1. Map /dev/dri/cardX to container
-        // 2. Create cgroup rules to allow /dev/dri/cardX access
-        if !arun_config.privilege() && arun_config.gui() {
-            let drm_device = vec!["/dev/dri/card0", "/dev/dri/card1"];
-
-            drm_device.iter().for_each(|&d| {
-                device_mapping.push(DeviceMapping {
-                    path_on_host: Some(d.to_string()),
-                    path_in_container: Some(d.to_string()),
-                    cgroup_permissions: Some("rwm".to_string()),
-                });
-            });
+        // 1. Map the existing /dev/dri/cardX and /dev/dri/renderDX to container
+        // 2. Create cgroup rules to allow their access
+        if !arun_config.privilege() && (arun_config.gui() || arun_config.gpu_render()) {
+            let drm_devices = drm::discover(drm::root(), !arun_config.gui())?;
+            if drm_devices.is_empty() {
+                jwarn!("No DRM device found for {}", arun_config.appid());
+            }
 
-            // Major number of /dev/dri/cardX
-            cgroup_rules.push("c 226:* rmw".to_owned());
+            for d in drm_devices {
+                // A device mapped explicitly in the config takes precedence.
+                if !device_mapping
+                    .iter()
+                    .any(|m| m.path_in_container.as_deref() == Some(d.path.as_str()))
+                {
+                    device_mapping.push(DeviceMapping {
+                        path_on_host: Some(d.path.clone()),
+                        path_in_container: Some(d.path.clone()),
+                        cgroup_permissions: Some("rwm".to_string()),
+                    });
+                }
+
+                let rule = d.cgroup_rule();
+                if !cgroup_rules.contains(&rule) {
+                    cgroup_rules.push(rule);
+                }
+            }
         }
 
         if arun_config.wayland() {
@@ -174,15 +446,15 @@ impl Runner {
 
         let mut pb: HashMap<String, Option<Vec<PortBinding>>> = HashMap::new();
         if let Some(ports) = arun_config.port_bindings() {
+            let re = Regex::new(r"([0-9|.]+):([0-9]+)")
+                .into_report()
+                .change_context(ArunError::Unknown)?;
+
             for p in ports {
                 let mut host: Vec<PortBinding> = Vec::new();
                 let key = p.port.clone();
 
                 for h in &p.host {
-                    let re = Regex::new(r"([0-9|.]+):([0-9]+)")
-                        .into_report()
-                        .change_context(ArunError::Unknown)?;
-
                     if !re.is_match(h) {
                         return Err(ArunError::InvalidValue)
                             .into_report()
@@ -204,34 +476,153 @@ impl Runner {
         Ok(HostConfig {
             binds: Some(binds),
             privileged: Some(arun_config.privilege()),
+            cap_add: Some(arun_config.cap_add().to_vec()),
+            cap_drop: Some(arun_config.cap_drop().to_vec()),
+            security_opt: Some(Runner::security_opt(arun_config)?),
+            readonly_rootfs: Some(arun_config.read_only_rootfs()),
+            tmpfs: Some(arun_config.tmpfs()),
+            userns_mode: arun_config.userns_mode().map(|u| u.to_string()),
             devices: Some(device_mapping),
             device_cgroup_rules: Some(cgroup_rules),
             network_mode: Some(arun_config.network().to_string()),
             port_bindings,
-            ..Default::default()
+            ..arun_config.resources().host_config()?
         })
     }
 
-    async fn update_state(&mut self) -> Result<(), ArunError> {
-        let container_name = self.config.appid();
-
-        let mut filters = HashMap::new();
-        filters.insert("name", vec![container_name.as_str()]);
-
+    async fn list_containers(
+        &self,
+        filters: HashMap<&str, Vec<&str>>,
+    ) -> Result<Vec<ContainerSummary>, ArunError> {
         let options = container::ListContainersOptions {
             all: true,
             filters,
             ..Default::default()
         };
 
-        let summary = self
-            .docker
+        self.docker
             .list_containers(Some(options))
             .await
             .into_report()
-            .change_context(ArunError::DockerErr)?;
+            .change_context(ArunError::DockerErr)
+    }
+
+    // The containers of the app are found by label, the name filter of docker matches
+    // substrings, e.g. sys.home would match sys.homeapp too. Containers created before
+    // the labels were stamped are found by their exact name.
+    async fn containers(&self) -> Result<Vec<ContainerSummary>, ArunError> {
+        let appid = self.config.appid();
+        let label = format!("{}={}", LABEL_APPID, appid);
+
+        let mut filters = HashMap::new();
+        filters.insert("label", vec![label.as_str()]);
+        let summary = self.list_containers(filters).await?;
+        if !summary.is_empty() {
+            return Ok(summary);
+        }
+
+        let name = format!("/{}", appid);
+        let mut filters = HashMap::new();
+        filters.insert("name", vec![appid.as_str()]);
+
+        Ok(self
+            .list_containers(filters)
+            .await?
+            .into_iter()
+            .filter(|c| c.names.as_ref().is_some_and(|n| n.contains(&name)))
+            .collect())
+    }
+
+    fn labels(&self) -> HashMap<String, String> {
+        HashMap::from([
+            (LABEL_APPID.to_string(), self.config.appid()),
+            (LABEL_CONFIG_HASH.to_string(), self.config.config_hash()),
+            (
+                LABEL_VERSION.to_string(),
+                env!("CARGO_PKG_VERSION").to_string(),
+            ),
+        ])
+    }
+
+    fn spec(&self) -> ContainerSpec {
+        ContainerSpec {
+            image: self.config.image(),
+            config_hash: Some(self.config.config_hash()),
+        }
+    }
+
+    fn set_drift(&mut self, drift: Option<ContainerSpec>) {
+        if let Some(d) = &drift {
+            if self.drift.as_ref() != Some(d) {
+                if d.config_hash.is_none() {
+                    // A container created before the labels were stamped can not be
+                    // compared with the config, it is handled as drifted so that it is
+                    // created again with the labels.
+                    jwarn!(
+                        "Container {} has no {} label, created from image {} by an older arun, handled as drifted ({:?})",
+                        self.config.appid(),
+                        LABEL_CONFIG_HASH,
+                        d.image,
+                        self.config.on_drift()
+                    );
+                } else {
+                    jwarn!(
+                        "Container {} drifted from its config, created from {}, configured {}",
+                        self.config.appid(),
+                        d,
+                        self.spec()
+                    );
+                }
+                jdebug!("Config of {}:\n{:?}", self.config.appid(), self.config);
+            }
+        }
+
+        self.drift = drift;
+    }
+
+    // Deal with a container created from another image or config as the config asks.
+    async fn resolve_drift(&mut self) -> Result<(), ArunError> {
+        let Some(drift) = self.drift.clone() else {
+            return Ok(());
+        };
+
+        let appid = self.config.appid();
+        match self.config.on_drift() {
+            DriftAction::Adopt => {}
+            DriftAction::Refuse => {
+                return Err(ArunError::ConflictedWithOther)
+                    .into_report()
+                    .attach_printable(format!(
+                        "Container {} was created from {}, refused to replace it with {}",
+                        appid,
+                        drift,
+                        self.spec()
+                    ));
+            }
+            DriftAction::Replace => {
+                jinfo!("Replace container {}: {} -> {}", appid, drift, self.spec());
+
+                // Docker does not stop a paused container, it is unpaused first.
+                if self.state == RunnerState::Paused {
+                    self.unpause().await?;
+                }
+                if matches!(self.state, RunnerState::Running | RunnerState::Restarting) {
+                    self.stop().await?;
+                }
+                self.remove().await?;
+                self.drift = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_state(&mut self) -> Result<(), ArunError> {
+        let container_name = self.config.appid();
+        let summary = self.containers().await?;
 
         let mut state = RunnerState::NonExist;
+        let mut drift = None;
 
         if summary.is_empty() {
             jdebug!("No container found for {}", container_name);
@@ -239,37 +630,38 @@ impl Runner {
             jdebug!("found container with name of {}", container_name);
         }
 
+        let config_spec = self.spec();
         for c in summary {
             let image = c.image.ok_or(ArunError::DockerErr).into_report()?;
-            if image == self.config.image() {
-                let s = c.state.ok_or(ArunError::DockerErr).into_report()?;
-                let new_state = RunnerState::from_str(s.as_str())
+            let s = c.state.ok_or(ArunError::DockerErr).into_report()?;
+            let new_state = RunnerState::from_str(s.as_str())
+                .into_report()
+                .attach_printable(format!("Failed to recognize state {}", s))?;
+
+            if state != RunnerState::NonExist && state != new_state {
+                return Err(ArunError::Unknown)
                     .into_report()
-                    .attach_printable(format!("Failed to recognize state {}", s))?;
+                    .attach_printable(format!(
+                        "Two state {} vs {} found for container with name {}.",
+                        state, new_state, container_name
+                    ));
+            }
 
-                if state != RunnerState::NonExist && state != new_state {
-                    return Err(ArunError::Unknown)
-                        .into_report()
-                        .attach_printable(format!(
-                            "Two state {} vs {} found for container with name {}.",
-                            state, new_state, container_name
-                        ));
-                }
+            // The config hash covers the image, the image is kept to be logged.
+            let spec = ContainerSpec {
+                image,
+                config_hash: c.labels.and_then(|mut l| l.remove(LABEL_CONFIG_HASH)),
+            };
 
-                state = new_state;
-                continue;
+            if spec.config_hash != config_spec.config_hash {
+                drift = Some(spec);
             }
 
-            return Err(ArunError::DockerErr)
-                .into_report()
-                .change_context(ArunError::DockerErr)
-                .attach_printable(format!(
-                    "Another container with image {} is running with name {}",
-                    image, container_name
-                ));
+            state = new_state;
         }
 
-        self.state = state;
+        self.set_drift(drift);
+        self.set_state(state);
         Ok(())
     }
 
@@ -339,9 +731,233 @@ impl Runner {
         Ok(id)
     }
 
-    pub async fn new(json: &str, monitor_interval: Option<u32>) -> Result<Self, ArunError> {
-        let arun_config = ArunConfig::parse(json, monitor_interval)?;
+    fn set_state(&mut self, state: RunnerState) {
+        if state == RunnerState::Running && self.state != RunnerState::Running {
+            self.started_at = Some(Instant::now());
+        }
+
+        self.state = state;
+        self.registry.update_state(&self.config.appid(), state);
+
+        if self.config.health_check().is_none() {
+            self.set_health(if state == RunnerState::Running {
+                RunnerHealth::Healthy
+            } else {
+                RunnerHealth::Unknown
+            });
+        } else if state != RunnerState::Running {
+            self.health_failures = 0;
+            if self.health == RunnerHealth::Healthy {
+                self.set_health(RunnerHealth::Unknown);
+            }
+        }
+    }
+
+    fn set_health(&mut self, health: RunnerHealth) {
+        if self.health != health {
+            jinfo!(
+                app = self.config.appid(),
+                NewHealth = health.to_string(),
+                OldHealth = self.health.to_string()
+            );
+        }
+
+        self.health = health;
+        self.registry.update_health(&self.config.appid(), health);
+    }
+
+    // Probe a running app and restart it after too many consecutive failures.
+    async fn check_health(&mut self) -> Result<(), ArunError> {
+        let check = match self.config.health_check() {
+            Some(c) => c.clone(),
+            None => return Ok(()),
+        };
+
+        if self.state != RunnerState::Running || self.shutting_down {
+            return Ok(());
+        }
+
+        match health::probe(&self.docker, &self.config.appid(), &check).await {
+            Ok(()) => {
+                self.health_failures = 0;
+                self.set_health(RunnerHealth::Healthy);
+            }
+            Err(e) => {
+                self.health_failures += 1;
+                jwarn!(
+                    "Health check of {} failed ({}/{}): {:?}",
+                    self.config.appid(),
+                    self.health_failures,
+                    check.retries(),
+                    e
+                );
+
+                if self.health_failures >= check.retries() {
+                    self.set_health(RunnerHealth::Unhealthy);
+
+                    // The app is started again by the monitor timer as the target state
+                    // stays Running.
+                    jwarn!("Restart unhealthy app {}", self.config.appid());
+                    self.stopped_unhealthy = true;
+                    self.state_transition(RunnerState::Exited).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Check whether all the conditions in depends_on are met.
+    fn dependencies_ready(&self) -> bool {
+        for d in self.config.depends_on() {
+            let ready = match d {
+                DependsOn::Started(appid) => {
+                    self.registry.state(appid) == Some(RunnerState::Running)
+                }
+                DependsOn::Healthy(appid) => {
+                    self.registry.state(appid) == Some(RunnerState::Running)
+                        && self.registry.health(appid) == Some(RunnerHealth::Healthy)
+                }
+                DependsOn::SocketExists(path) => Path::new(path)
+                    .metadata()
+                    .map(|m| m.file_type().is_socket())
+                    .unwrap_or(false),
+            };
+
+            if !ready {
+                jinfo!("{} is waiting for {}", self.config.appid(), d);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Check whether all the apps depending on this one have been stopped.
+    fn dependents_stopped(&self) -> bool {
+        for (appid, state) in self.registry.dependents(&self.config.appid()) {
+            if matches!(
+                state,
+                RunnerState::Running | RunnerState::Restarting | RunnerState::Paused
+            ) {
+                jinfo!("{} is waiting for {} to stop", self.config.appid(), appid);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    async fn exit_code(&self) -> Result<i64, ArunError> {
+        let inspect = self
+            .docker
+            .inspect_container(&self.config.appid(), None)
+            .await
+            .into_report()
+            .change_context(ArunError::DockerErr)?;
+
+        Ok(inspect.state.and_then(|s| s.exit_code).unwrap_or(0))
+    }
+
+    // Apply the restart policy to an app which should be running but is not. Returns
+    // true if the app may be (re)started now.
+    async fn restart_allowed(&mut self) -> Result<bool, ArunError> {
+        if self.target_state != RunnerState::Running {
+            return Ok(true);
+        }
+
+        if self.quarantined {
+            return Ok(false);
+        }
+
+        // Only an app exited after having been started is subject to the restart policy,
+        // the start time is kept until then.
+        if matches!(self.state, RunnerState::Exited | RunnerState::Dead) {
+            if let Some(started_at) = self.started_at.take() {
+                let policy = self.config.restart_policy();
+                let appid = self.config.appid();
+
+                if policy.resets_failures(started_at.elapsed()) {
+                    self.failures = 0;
+                }
+
+                let exit_code = self.exit_code().await?;
+                let failed = std::mem::take(&mut self.stopped_unhealthy)
+                    || self.state == RunnerState::Dead
+                    || exit_code != 0;
+
+                let restart = match policy.mode() {
+                    RestartMode::Always => true,
+                    RestartMode::OnFailure => failed,
+                    RestartMode::Never => false,
+                };
+
+                if !restart {
+                    jinfo!(
+                        "{} exited with {}, not restarted by {:?} policy",
+                        appid,
+                        exit_code,
+                        policy.mode()
+                    );
+                    self.target_state = RunnerState::Exited;
+                    return Ok(false);
+                }
+
+                self.failures += 1;
+                if self.failures > policy.max_retries() {
+                    jerror!(
+                        "{} exited {} times in a row, quarantined until cleared",
+                        appid,
+                        self.failures
+                    );
+                    self.quarantined = true;
+                    return Ok(false);
+                }
+
+                let delay = policy.backoff(self.failures);
+                jwarn!(
+                    "{} exited with {}, restart {}/{} in {}s",
+                    appid,
+                    exit_code,
+                    self.failures,
+                    policy.max_retries(),
+                    delay
+                );
+                self.restart_at = Some(Instant::now() + Duration::from_secs(delay as u64));
+            }
+        }
+
+        if let Some(restart_at) = self.restart_at {
+            if Instant::now() < restart_at {
+                return Ok(false);
+            }
+
+            self.restart_at = None;
+        }
+
+        Ok(true)
+    }
+
+    fn clear_quarantine(&mut self) {
+        if self.quarantined {
+            jinfo!("{} is released from quarantine", self.config.appid());
+        }
+
+        self.quarantined = false;
+        self.failures = 0;
+        self.restart_at = None;
+    }
+
+    pub async fn new(
+        arun_config: ArunConfig,
+        registry: AppRegistry,
+        policy: Policy,
+    ) -> Result<Self, ArunError> {
         jdebug!("Arun Config:\n{:?}", arun_config);
+        policy.check(&arun_config)?;
+        for w in arun_config.security_warnings() {
+            jwarn!("{}", w);
+        }
 
         let mut app = Docker::connect_with_socket_defaults()
             .into_report()
@@ -353,16 +969,119 @@ impl Runner {
             config: arun_config,
             docker: app,
             state: RunnerState::NonExist,
+            health: RunnerHealth::Unknown,
+            health_failures: 0,
             target_state: RunnerState::NonExist,
             network_id,
+            registry,
+            policy,
+            shutting_down: false,
+            started_at: None,
+            stopped_unhealthy: false,
+            drift: None,
+            failures: 0,
+            restart_at: None,
+            quarantined: false,
         };
 
+        runner
+            .registry
+            .register(&runner.config.appid(), runner.dependency_ids());
+
         runner.update_state().await?;
         jdebug!(InitialContainerState = runner.state.to_string());
 
         Ok(runner)
     }
 
+    pub fn appid(&self) -> String {
+        self.config.appid()
+    }
+
+    fn dependency_ids(&self) -> Vec<String> {
+        self.config
+            .depends_on()
+            .iter()
+            .filter_map(|d| d.appid().map(|a| a.to_string()))
+            .collect()
+    }
+
+    // Apply a changed config. The container is recreated only if a setting used to
+    // create it has changed, otherwise the new settings take effect in place. A change
+    // failing to apply is logged and the app keeps running with its previous config.
+    // Returns whether the new config has been applied.
+    async fn reload(&mut self, config: ArunConfig) -> bool {
+        let appid = self.config.appid();
+
+        if let Err(e) = self.policy.check(&config) {
+            jerror!("Config change of {} rejected: {:?}", appid, e);
+            return false;
+        }
+
+        let change = self.config.change(&config);
+        if change != ConfigChange::Unchanged {
+            for w in config.security_warnings() {
+                jwarn!("{}", w);
+            }
+        }
+
+        match change {
+            ConfigChange::Unchanged => true,
+            ConfigChange::Runtime => {
+                jinfo!("{} config updated", appid);
+                self.config = config;
+                self.registry
+                    .update_depends_on(&appid, self.dependency_ids());
+                true
+            }
+            ConfigChange::Create => {
+                // Get the new image before the running container is removed, a config
+                // whose image can not be installed or verified is rejected.
+                let old = std::mem::replace(&mut self.config, config);
+                let prepared = self.prepare_image().await;
+                let new = std::mem::replace(&mut self.config, old);
+                if let Err(e) = prepared {
+                    jerror!("Config change of {} rejected: {:?}", appid, e);
+                    return false;
+                }
+
+                jinfo!("{} config changed, recreate the container", appid);
+
+                let old = self.config.clone();
+                if let Err(e) = self.recreate(new).await {
+                    jerror!(
+                        "Failed to apply the config change of {}, keep the previous config: {:?}",
+                        appid,
+                        e
+                    );
+
+                    // The container of the previous config may be gone already, or one
+                    // of the new config left behind, it is recreated as a drift.
+                    self.config = old;
+                    self.registry
+                        .update_depends_on(&appid, self.dependency_ids());
+                    if let Err(e) = self.state_transition(self.target_state).await {
+                        jerror!("Failed to restore {}: {:?}", appid, e);
+                    }
+                    return false;
+                }
+
+                true
+            }
+        }
+    }
+
+    // The old config is still needed to remove the container.
+    async fn recreate(&mut self, config: ArunConfig) -> Result<(), ArunError> {
+        self.started_at = None;
+        self.state_transition(RunnerState::NonExist).await?;
+
+        self.config = config;
+        self.registry
+            .update_depends_on(&self.config.appid(), self.dependency_ids());
+        self.state_transition(self.target_state).await
+    }
+
     pub async fn create(&mut self) -> Result<(), ArunError> {
         let mut ip_address = None;
 
@@ -384,7 +1103,7 @@ impl Runner {
 
         let option = container::CreateContainerOptions {
             name: self.config.appid(),
-            platform: None,
+            platform: Some(self.config.platform().to_string()),
         };
 
         let mut env = self.config.environment();
@@ -396,11 +1115,15 @@ impl Runner {
 
         let config = container::Config {
             image: Some(self.config.image()),
-            cmd: Some(self.config.cmd()),
+            cmd: Some(self.config.cmd()?),
+            entrypoint: self.config.entrypoint()?,
+            working_dir: self.config.working_dir().map(|w| w.to_string()),
+            user: self.config.user().map(|u| u.to_string()),
             env: Some(env),
             host_config: Some(Runner::host_config(&self.config)?),
             network_disabled: Some(self.config.network() == "none"),
             networking_config: Some(container::NetworkingConfig { endpoints_config }),
+            labels: Some(self.labels()),
             ..Default::default()
         };
 
@@ -410,7 +1133,7 @@ impl Runner {
             .into_report()
             .change_context(ArunError::DockerErr)?;
 
-        self.state = RunnerState::Created;
+        self.set_state(RunnerState::Created);
 
         Ok(())
     }
@@ -424,7 +1147,7 @@ impl Runner {
             .into_report()
             .change_context(ArunError::DockerErr)?;
 
-        self.state = RunnerState::Running;
+        self.set_state(RunnerState::Running);
         Ok(())
     }
 
@@ -438,7 +1161,7 @@ impl Runner {
             .into_report()
             .change_context(ArunError::DockerErr)?;
 
-        self.state = RunnerState::Exited;
+        self.set_state(RunnerState::Exited);
         Ok(())
     }
 
@@ -455,7 +1178,7 @@ impl Runner {
                 container_name
             ))?;
 
-        self.state = RunnerState::Paused;
+        self.set_state(RunnerState::Paused);
         Ok(())
     }
 
@@ -472,7 +1195,7 @@ impl Runner {
                 container_name
             ))?;
 
-        self.state = RunnerState::Running;
+        self.set_state(RunnerState::Running);
         Ok(())
     }
 
@@ -494,17 +1217,28 @@ impl Runner {
                 container_name
             ))?;
 
-        self.state = RunnerState::NonExist;
+        self.set_state(RunnerState::NonExist);
 
         Ok(())
     }
 
     pub async fn state_transition(&mut self, target: RunnerState) -> Result<(), ArunError> {
         self.update_state().await?;
+        if target != RunnerState::NonExist {
+            self.resolve_drift().await?;
+        }
         if self.state == target {
             return Ok(());
         }
 
+        if target == RunnerState::Running && !self.dependencies_ready() {
+            return Ok(());
+        }
+
+        if self.shutting_down && !self.dependents_stopped() {
+            return Ok(());
+        }
+
         if target == RunnerState::NonExist {
             match self.state {
                 RunnerState::NonExist => {}
@@ -584,8 +1318,6 @@ impl Runner {
             }
         }
 
-        if target == RunnerState::Restarting {}
-
         if target == RunnerState::Exited {
             match self.state {
                 RunnerState::NonExist => {
@@ -668,12 +1400,145 @@ impl Runner {
             .change_context(ArunError::DockerErr)
     }
 
-    pub async fn run(&mut self) -> Result<(), ArunError> {
-        if !self.find_image().await? {
-            jinfo!("Install image {}", self.config.image());
-            self.install().await?;
+    pub async fn inspect(&self) -> Result<serde_json::Value, ArunError> {
+        let inspect = self
+            .docker
+            .inspect_container(&self.config.appid(), None)
+            .await
+            .into_report()
+            .change_context(ArunError::DockerErr)?;
+
+        serde_json::to_value(inspect)
+            .into_report()
+            .change_context(ArunError::InvalidValue)
+    }
+
+    // Apply a control command. The state transition is done before replying so that
+    // the response tells its outcome.
+    async fn handle_cmd(
+        &mut self,
+        cmd: &ArunCtrlCmd,
+    ) -> Result<Option<serde_json::Value>, ArunError> {
+        // A crash looping app is only started again once its quarantine is cleared.
+        if self.quarantined && matches!(cmd, ArunCtrlCmd::Start | ArunCtrlCmd::Restart) {
+            return Err(ArunError::Quarantined)
+                .into_report()
+                .attach_printable(format!(
+                    "{} is quarantined after {} failures, clear the quarantine to start it",
+                    self.config.appid(),
+                    self.failures
+                ));
+        }
+
+        let target = match cmd {
+            ArunCtrlCmd::Start | ArunCtrlCmd::Unpause => RunnerState::Running,
+            ArunCtrlCmd::Stop => RunnerState::Exited,
+            ArunCtrlCmd::Remove => RunnerState::NonExist,
+            ArunCtrlCmd::Pause => RunnerState::Paused,
+            ArunCtrlCmd::Restart => {
+                self.started_at = None;
+                self.state_transition(RunnerState::Exited).await?;
+                RunnerState::Running
+            }
+            ArunCtrlCmd::Inspect => return self.inspect().await.map(Some),
+            ArunCtrlCmd::ClearQuarantine => {
+                self.clear_quarantine();
+                return Ok(None);
+            }
+            ArunCtrlCmd::Status | ArunCtrlCmd::Quit => return Ok(None),
+            ArunCtrlCmd::Invalid => {
+                return Err(ArunError::InvalidValue)
+                    .into_report()
+                    .attach_printable("Invalid command")
+            }
+        };
+
+        // An app stopped on request is not subject to the restart policy.
+        self.started_at = None;
+        self.target_state = target;
+        self.state_transition(target).await?;
+
+        Ok(None)
+    }
+
+    fn response(
+        &self,
+        id: u64,
+        result: Result<Option<serde_json::Value>, ArunError>,
+    ) -> ArunCtrlResponse {
+        let (inspect, error) = match result {
+            Ok(inspect) => (inspect, None),
+            Err(e) => (None, Some(format!("{:?}", e))),
+        };
+
+        ArunCtrlResponse {
+            id,
+            appid: self.config.appid(),
+            image: self.config.image(),
+            state: self.state,
+            target_state: self.target_state,
+            health: self.health,
+            quarantined: self.quarantined,
+            error,
+            inspect,
+        }
+    }
+
+    fn events(&self) -> EventStream {
+        let container_name = self.config.appid();
+
+        let mut filters = HashMap::new();
+        filters.insert("type".to_string(), vec!["container".to_string()]);
+        filters.insert("container".to_string(), vec![container_name]);
+
+        let options = system::EventsOptions::<String> {
+            filters,
+            ..Default::default()
+        };
+
+        Box::pin(self.docker.events(Some(options)))
+    }
+
+    // Update the state according to a docker event of the container.
+    fn handle_event(&mut self, event: EventMessage) {
+        let container_name = self.config.appid();
+
+        // The container filter of docker events is not an exact match.
+        let name = event
+            .actor
+            .as_ref()
+            .and_then(|a| a.attributes.as_ref())
+            .and_then(|a| a.get("name"));
+
+        if name != Some(&container_name) {
+            return;
         }
 
+        let action = event.action.unwrap_or_default();
+        jdebug!(app = container_name, event = action);
+
+        let state = match action.as_str() {
+            "create" => RunnerState::Created,
+            "start" | "unpause" | "restart" => RunnerState::Running,
+            "pause" => RunnerState::Paused,
+            "die" => RunnerState::Exited,
+            "destroy" => RunnerState::NonExist,
+            "oom" => {
+                jwarn!("{} is out of memory", container_name);
+                return;
+            }
+            _ => return,
+        };
+
+        self.set_state(state);
+    }
+
+    pub async fn run(
+        &mut self,
+        mut updates: mpsc::Receiver<RunnerUpdate>,
+    ) -> Result<(), ArunError> {
+        self.prepare_image().await?;
+
         // Set initial target state to Running
         self.target_state = RunnerState::Running;
 
@@ -683,31 +1548,49 @@ impl Runner {
         let mut old_state = self.state;
         let mut ctrl = ArunCtrl::create(&self.config.appid()).await?;
         let (sx, mut rx) = mpsc::channel::<RunnerRequest>(3);
+        let mut shutdown = self.registry.subscribe_shutdown();
+        let mut htimer = IntervalTimer::new(tokio::time::Duration::from_secs(
+            self.config
+                .health_check()
+                .map(|c| c.interval())
+                .unwrap_or(u32::MAX) as u64,
+        ));
+        let mut ptimer = IntervalTimer::new(tokio::time::Duration::from_secs(
+            STATE_POLL_INTERVAL_S.max(self.config.monitor_interval()) as u64,
+        ));
+
+        // Subscribe before syncing so that no state change is missed in between.
+        let mut events = self.events();
+        let mut resync = Resync::default();
+        self.update_state().await?;
+        // A container left from another config is dealt with whatever its state, the
+        // state transitions may never happen if it already is in the target state.
+        self.resolve_drift().await?;
 
         loop {
             tokio::select! {
                 cmd = ctrl.wait_cmd() => {
-                    let mut quit = false;
-
-                    match cmd.unwrap_or(ArunCtrlCmd::Quit) {
-                                ArunCtrlCmd::Quit => quit = true,
-                                ArunCtrlCmd::Start =>
-                                    self.target_state =RunnerState::Running,
-                                ArunCtrlCmd::Stop =>
-                                    self.target_state = RunnerState::Exited,
-                                ArunCtrlCmd::Remove =>
-                                    self.target_state = RunnerState::NonExist,
-                                _=> {},
+                    let msg = match cmd {
+                        Ok(m) => m,
+                        Err(_) => {
+                            ctrl.exit().await;
+                            break;
+                        }
+                    };
+
+                    let quit = matches!(msg.cmd, ArunCtrlCmd::Quit);
+                    let result = self.handle_cmd(&msg.cmd).await;
+                    if let Err(e) = &result {
+                        jwarn!("{} failed: {:?}", msg.cmd, e);
                     }
 
+                    let response = self.response(msg.id.unwrap_or(0), result);
+                    msg.reply(response);
+
                     if quit {
                         ctrl.exit().await;
                         break;
                     }
-
-                    sx.send(RunnerRequest::UpdateState)
-                        .await.into_report()
-                        .change_context(ArunError::IOError)?
                 }
 
                 request = rx.recv() => {
@@ -720,9 +1603,90 @@ impl Runner {
                     }
                 }
 
+                Some(update) = updates.recv(), if !self.shutting_down => {
+                    match update {
+                        RunnerUpdate::Config(config, applied) => {
+                            let _ = applied.send(self.reload(*config).await);
+
+                            itimer.update_interval(Duration::from_secs(
+                                self.config.monitor_interval() as u64,
+                            ));
+                            htimer.update_interval(Duration::from_secs(
+                                self.config
+                                    .health_check()
+                                    .map(|c| c.interval())
+                                    .unwrap_or(u32::MAX) as u64,
+                            ));
+                            ptimer.update_interval(Duration::from_secs(
+                                STATE_POLL_INTERVAL_S.max(self.config.monitor_interval()) as u64,
+                            ));
+                        }
+                        RunnerUpdate::Remove => {
+                            jinfo!("{} removed from the config", self.config.appid());
+                            self.target_state = RunnerState::NonExist;
+                            self.state_transition(RunnerState::NonExist).await?;
+                            ctrl.exit().await;
+                            break;
+                        }
+                    }
+                }
+
+                _ = shutdown.changed(), if !self.shutting_down => {
+                    // Stop the app once all apps depending on it have been stopped, the
+                    // state transition is retried by the monitor timer until then.
+                    jinfo!("Shutting down {}", self.config.appid());
+                    self.shutting_down = true;
+                    self.target_state = RunnerState::Exited;
+                }
+
+                _ = htimer.wait_timeup(), if self.config.health_check().is_some() => {
+                    self.check_health().await?;
+                }
+
+                event = events.next(), if !resync.pending() => {
+                    match event {
+                        Some(Ok(e)) => self.handle_event(e),
+                        Some(Err(e)) => {
+                            jwarn!("Docker events stream error: {:?}", e);
+                            resync.broken();
+                        }
+                        None => {
+                            jwarn!("Docker events stream closed");
+                            resync.broken();
+                        }
+                    }
+                }
+
+                // The events missed while the stream was broken are made up for by
+                // syncing the state once it is reconnected.
+                _ = resync.wait(), if resync.pending() => {
+                    events = self.events();
+                    if resync.synced(self.update_state().await) {
+                        jinfo!("Docker events stream reconnected");
+                    }
+                }
+
+                _ = ptimer.wait_timeup() => {
+                    transient(self.update_state().await)?;
+                    if self.drift.is_some() {
+                        transient(self.resolve_drift().await)?;
+                    }
+                }
+
                 _ = itimer.wait_timeup() => {
-                    self.update_state().await?;
-                    if self.state != self.target_state {
+                    if self.shutting_down
+                        && !matches!(
+                            self.state,
+                            RunnerState::Running | RunnerState::Restarting | RunnerState::Paused
+                        )
+                    {
+                        ctrl.exit().await;
+                        break;
+                    }
+
+                    if self.state != self.target_state
+                        && transient(self.restart_allowed().await)?
+                    {
                         sx
                             .send(RunnerRequest::UpdateState)
                             .await.into_report()
@@ -745,3 +1709,55 @@ impl Runner {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn docker_error() -> Result<(), ArunError> {
+        Err(Report::new(ArunError::DockerErr).attach_printable("Cannot connect to docker"))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resync_retries_with_capped_backoff() {
+        let mut resync = Resync::default();
+        assert!(!resync.pending());
+
+        resync.broken();
+        assert!(resync.pending());
+
+        // A second error of the broken stream does not push the attempt back.
+        let at = resync.at;
+        resync.broken();
+        assert_eq!(resync.at, at);
+
+        let start = Instant::now();
+        resync.wait().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(RESYNC_DELAY_MIN_S));
+
+        let mut delays = vec![];
+        for _ in 0..6 {
+            assert!(!resync.synced(docker_error()));
+            assert!(resync.pending());
+
+            let start = Instant::now();
+            resync.wait().await;
+            delays.push(start.elapsed().as_secs());
+        }
+        assert_eq!(delays, [2, 4, 8, 16, 30, 30]);
+
+        assert!(resync.synced(Ok(())));
+        assert!(!resync.pending());
+        assert_eq!(resync.delay, Duration::from_secs(RESYNC_DELAY_MIN_S));
+    }
+
+    #[test]
+    fn transient_docker_errors_are_retried() {
+        assert!(transient(docker_error()).is_ok());
+        assert!(!transient(Err::<bool, _>(Report::new(ArunError::DockerErr))).unwrap());
+        assert!(transient(Ok(true)).unwrap());
+
+        let refused: Result<(), ArunError> = Err(Report::new(ArunError::ConflictedWithOther));
+        assert!(transient(refused).is_err());
+    }
+}
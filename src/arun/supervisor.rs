@@ -0,0 +1,606 @@
+#[allow(unused)]
+use {
+    super::{
+        arun_config::{ArunConfig, ConfigFormat, ResourceDefaults},
+        interpolate,
+        policy::Policy,
+        registry::AppRegistry,
+        runner::{Runner, RunnerUpdate},
+        template,
+        watcher::ConfigWatcher,
+    },
+    arunlib::arun_error::ArunError,
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    std::{
+        collections::{HashMap, HashSet},
+        fs,
+        path::{Path, PathBuf},
+    },
+    tokio::{
+        signal::unix::{signal, SignalKind},
+        sync::{mpsc, oneshot},
+        task::JoinSet,
+    },
+};
+
+type RunnerTasks = JoinSet<(String, Result<(), ArunError>)>;
+
+pub struct Supervisor {
+    runners: Vec<Runner>,
+    registry: AppRegistry,
+    paths: Vec<String>,
+    monitor_interval: Option<u32>,
+    resource_defaults: ResourceDefaults,
+    policy: Policy,
+    // The configs being run, to find what changed when the config files are edited.
+    configs: HashMap<String, ArunConfig>,
+    updates: HashMap<String, mpsc::Sender<RunnerUpdate>>,
+    shutting_down: bool,
+}
+
+impl Supervisor {
+    // Collect the config files to load. A directory is expanded to the json, toml and yaml
+    // files it contains, sorted by name so that the start order is stable.
+    pub fn config_files(paths: &[String]) -> Result<Vec<PathBuf>, ArunError> {
+        let mut files = vec![];
+
+        for p in paths {
+            let path = Path::new(p);
+
+            if !path.is_dir() {
+                files.push(path.to_path_buf());
+                continue;
+            }
+
+            let mut entries = vec![];
+            for entry in fs::read_dir(path)
+                .into_report()
+                .change_context(ArunError::IOError)
+                .attach_printable(format!("Failed to read config directory {}", p))?
+            {
+                let entry = entry.into_report().change_context(ArunError::IOError)?;
+                let file = entry.path();
+
+                if file.is_file() && ConfigFormat::from_extension(&file).is_some() {
+                    entries.push(file);
+                }
+            }
+
+            entries.sort();
+            files.append(&mut entries);
+        }
+
+        Ok(files)
+    }
+
+    pub fn load_file(
+        file: &Path,
+        monitor_interval: Option<u32>,
+    ) -> Result<Vec<ArunConfig>, ArunError> {
+        let text = fs::read_to_string(file)
+            .into_report()
+            .change_context(ArunError::InvalidValue)
+            .attach_printable(format!("Failed to read {}", file.display()))?;
+
+        jdebug!("Config {}:\n{}", file.display(), text);
+
+        let format = ConfigFormat::detect(file, &text);
+        let dir = file.parent().unwrap_or_else(|| Path::new("."));
+        ArunConfig::parse_manifest(&text, format, dir, monitor_interval)
+            .attach_printable(format!("Invalid config file {}", file.display()))
+    }
+
+    // The env files read by the apps of the config files, to be watched with them. A
+    // config file failing to load is skipped, it is reported when the apps are loaded.
+    fn env_files(paths: &[String]) -> Vec<PathBuf> {
+        let mut env_files = vec![];
+
+        for file in Supervisor::config_files(paths).unwrap_or_default() {
+            let text = match fs::read_to_string(&file) {
+                Ok(t) => t,
+                Err(_) => continue,
+            };
+
+            let format = ConfigFormat::detect(&file, &text);
+            let dir = file.parent().unwrap_or_else(|| Path::new("."));
+            for entry in ArunConfig::manifest_entries(&text, format).unwrap_or_default() {
+                if let Ok(entry) = template::resolve(entry, dir) {
+                    env_files.append(&mut interpolate::env_files(&entry, dir));
+                }
+            }
+        }
+
+        env_files
+    }
+
+    pub fn load(
+        paths: &[String],
+        monitor_interval: Option<u32>,
+        resource_defaults: &ResourceDefaults,
+    ) -> Result<Vec<ArunConfig>, ArunError> {
+        let configs = Supervisor::load_apps(paths, monitor_interval, resource_defaults)?;
+
+        if let Some(e) = Supervisor::unknown_dependencies(&configs)
+            .into_iter()
+            .next()
+        {
+            return Err(ArunError::InvalidValue)
+                .into_report()
+                .attach_printable(e);
+        }
+
+        Supervisor::sort_by_dependency(configs)
+    }
+
+    // The apps of the config files with the resource defaults applied, in the order they
+    // are found and with their dependencies not checked.
+    pub fn load_apps(
+        paths: &[String],
+        monitor_interval: Option<u32>,
+        resource_defaults: &ResourceDefaults,
+    ) -> Result<Vec<ArunConfig>, ArunError> {
+        let mut configs = vec![];
+
+        for file in Supervisor::config_files(paths)? {
+            let mut c = Supervisor::load_file(&file, monitor_interval)?;
+            configs.append(&mut c);
+        }
+
+        if configs.is_empty() {
+            return Err(ArunError::InvalidValue)
+                .into_report()
+                .attach_printable(format!("No app config found in {:?}", paths));
+        }
+
+        let mut appids = HashSet::new();
+        for c in configs.iter_mut() {
+            c.apply_resource_defaults(resource_defaults)?;
+
+            if !appids.insert(c.appid()) {
+                return Err(ArunError::ConflictedWithOther)
+                    .into_report()
+                    .attach_printable(format!("Duplicated app {}", c.appid()));
+            }
+        }
+
+        Ok(configs)
+    }
+
+    // The dependencies on apps which are not among the configs.
+    pub fn unknown_dependencies(configs: &[ArunConfig]) -> Vec<String> {
+        let appids: HashSet<String> = configs.iter().map(|c| c.appid()).collect();
+
+        configs
+            .iter()
+            .flat_map(|c| {
+                c.depends_on()
+                    .iter()
+                    .filter_map(|d| d.appid())
+                    .filter(|appid| !appids.contains(*appid))
+                    .map(|appid| format!("{} depends on unknown app {}", c.appid(), appid))
+                    .collect::<Vec<String>>()
+            })
+            .collect()
+    }
+
+    // Order the configs so that every app comes after the apps it depends on. Unknown
+    // dependencies are not waited for, they are checked by unknown_dependencies().
+    pub fn sort_by_dependency(mut configs: Vec<ArunConfig>) -> Result<Vec<ArunConfig>, ArunError> {
+        let appids: HashSet<String> = configs.iter().map(|c| c.appid()).collect();
+
+        let mut sorted: Vec<ArunConfig> = vec![];
+        while !configs.is_empty() {
+            let pos = configs.iter().position(|c| {
+                c.depends_on()
+                    .iter()
+                    .filter_map(|d| d.appid())
+                    .filter(|appid| appids.contains(*appid))
+                    .all(|appid| sorted.iter().any(|s| s.appid() == appid))
+            });
+
+            match pos {
+                Some(p) => sorted.push(configs.remove(p)),
+                None => {
+                    let remaining: Vec<String> = configs.iter().map(|c| c.appid()).collect();
+                    return Err(ArunError::InvalidValue)
+                        .into_report()
+                        .attach_printable(format!(
+                            "Circular dependency found among {}",
+                            remaining.join(", ")
+                        ));
+                }
+            }
+        }
+
+        Ok(sorted)
+    }
+
+    // The apps the policy allows. An app refused by the policy is left out rather than
+    // keeping all the others from running, as when it is added by a config change.
+    fn allowed(configs: Vec<ArunConfig>, policy: &Policy) -> Result<Vec<ArunConfig>, ArunError> {
+        let mut allowed = vec![];
+
+        for c in configs {
+            match policy.check(&c) {
+                Ok(()) => allowed.push(c),
+                Err(e) if matches!(e.current_context(), ArunError::PolicyViolation) => {
+                    jerror!("Failed to add {}: {:?}", c.appid(), e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(allowed)
+    }
+
+    pub async fn new(
+        paths: &[String],
+        monitor_interval: Option<u32>,
+        resource_defaults: ResourceDefaults,
+        policy: Policy,
+    ) -> Result<Self, ArunError> {
+        let configs = Supervisor::load(paths, monitor_interval, &resource_defaults)?;
+        let registry = AppRegistry::default();
+        let mut runners = vec![];
+        let mut running = HashMap::new();
+
+        for c in Supervisor::allowed(configs, &policy)? {
+            let runner = Runner::new(c.clone(), registry.clone(), policy.clone()).await?;
+            runners.push(runner);
+            running.insert(c.appid(), c);
+        }
+
+        Ok(Self {
+            runners,
+            registry,
+            paths: paths.to_vec(),
+            monitor_interval,
+            resource_defaults,
+            policy,
+            configs: running,
+            updates: HashMap::new(),
+            shutting_down: false,
+        })
+    }
+
+    fn spawn(&mut self, tasks: &mut RunnerTasks, mut runner: Runner) {
+        let registry = self.registry.clone();
+        let (sx, rx) = mpsc::channel::<RunnerUpdate>(3);
+
+        self.updates.insert(runner.appid(), sx);
+        tasks.spawn(async move {
+            let appid = runner.appid();
+            let result = runner.run(rx).await;
+
+            registry.unregister(&appid);
+            (appid, result)
+        });
+    }
+
+    async fn send_update(&self, appid: &str, update: RunnerUpdate) {
+        if let Some(sx) = self.updates.get(appid) {
+            if sx.send(update).await.is_err() {
+                jwarn!("{} is not running, config change dropped", appid);
+            }
+        }
+    }
+
+    // Send a changed config to the runner of the app, true once the app runs with it.
+    async fn send_config(&self, appid: &str, config: ArunConfig) -> bool {
+        let (sx, rx) = oneshot::channel();
+        self.send_update(appid, RunnerUpdate::Config(Box::new(config), sx))
+            .await;
+
+        rx.await.unwrap_or(false)
+    }
+
+    // Apply the edited config files. An invalid edit is rejected as a whole and the
+    // running config is kept.
+    async fn reload(&mut self, tasks: &mut RunnerTasks) {
+        let configs =
+            match Supervisor::load(&self.paths, self.monitor_interval, &self.resource_defaults) {
+                Ok(c) => c,
+                Err(e) => {
+                    jerror!(
+                        "Invalid config change rejected, keep the running config: {:?}",
+                        e
+                    );
+                    return;
+                }
+            };
+
+        let appids: HashSet<String> = configs.iter().map(|c| c.appid()).collect();
+        let removed: Vec<String> = self
+            .configs
+            .keys()
+            .filter(|a| !appids.contains(*a))
+            .cloned()
+            .collect();
+
+        for appid in removed {
+            self.send_update(&appid, RunnerUpdate::Remove).await;
+            self.updates.remove(&appid);
+            self.configs.remove(&appid);
+        }
+
+        for c in configs {
+            let appid = c.appid();
+
+            match self.configs.get(&appid) {
+                Some(old) if old == &c => {}
+                // A change the runner rejects is sent again with the next edit.
+                Some(_) => {
+                    if self.send_config(&appid, c.clone()).await {
+                        self.configs.insert(appid, c);
+                    }
+                }
+                None => {
+                    match Runner::new(c.clone(), self.registry.clone(), self.policy.clone()).await {
+                        Ok(runner) => {
+                            jinfo!("{} added to the config", appid);
+                            self.spawn(tasks, runner);
+                            self.configs.insert(appid, c);
+                        }
+                        Err(e) => jerror!("Failed to add {}: {:?}", appid, e),
+                    }
+                }
+            }
+        }
+    }
+
+    // Run every app in its own task. A failing app does not stop the others, the
+    // error is reported once all apps have finished.
+    // On SIGTERM or SIGINT all apps are stopped in reverse dependency order.
+    // The config files are watched and their changes applied to the running apps.
+    pub async fn run(mut self) -> Result<(), ArunError> {
+        let mut tasks = JoinSet::new();
+
+        for runner in std::mem::take(&mut self.runners) {
+            self.spawn(&mut tasks, runner);
+        }
+
+        let mut watcher = ConfigWatcher::new(&self.paths)?;
+        watcher.watch_env_files(Supervisor::env_files(&self.paths));
+        let mut watching = true;
+
+        let mut sigterm = signal(SignalKind::terminate())
+            .into_report()
+            .change_context(ArunError::IOError)?;
+        let mut sigint = signal(SignalKind::interrupt())
+            .into_report()
+            .change_context(ArunError::IOError)?;
+
+        let mut result = Ok(());
+        loop {
+            let joined = tokio::select! {
+                _ = sigterm.recv() => {
+                    jinfo!("SIGTERM received, stopping all apps");
+                    self.shutting_down = true;
+                    self.registry.shutdown();
+                    continue;
+                }
+
+                _ = sigint.recv() => {
+                    jinfo!("SIGINT received, stopping all apps");
+                    self.shutting_down = true;
+                    self.registry.shutdown();
+                    continue;
+                }
+
+                changed = watcher.changed(), if watching && !self.shutting_down => {
+                    match changed {
+                        Ok(()) => {
+                            jinfo!("Config changed, reloading");
+                            self.reload(&mut tasks).await;
+                            watcher.watch_env_files(Supervisor::env_files(&self.paths));
+                        }
+                        Err(e) => {
+                            jerror!("Stop watching the config: {:?}", e);
+                            watching = false;
+                        }
+                    }
+                    continue;
+                }
+
+                joined = tasks.join_next() => match joined {
+                    Some(j) => j,
+                    None => break,
+                },
+            };
+
+            match joined {
+                Ok((appid, Ok(()))) => jinfo!("{} quit", appid),
+                Ok((appid, Err(e))) => {
+                    jerror!("{} quit with error: {:?}", appid, e);
+                    result = Err(e);
+                }
+                Err(e) => {
+                    jerror!("Runner task failed: {:?}", e);
+                    result = Err(ArunError::Unknown)
+                        .into_report()
+                        .attach_printable(format!("Runner task failed: {}", e));
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn config(name: &str, depends_on: &[&str]) -> ArunConfig {
+        let depends_on: Vec<serde_json::Value> = depends_on
+            .iter()
+            .map(|d| json!({ "Started": format!("sys.{}", d) }))
+            .collect();
+
+        serde_json::from_value(json!({
+            "name": name,
+            "app_type": "Sys",
+            "image": "joukan/app",
+            "version": "0.1",
+            "privilege": false,
+            "network": "none",
+            "cmd": ["/bin/app"],
+            "binds": [],
+            "features": [],
+            "environments": [],
+            "depends_on": depends_on
+        }))
+        .unwrap()
+    }
+
+    // A supervisor running sys.a with the config given, and the updates sent to its
+    // runner, which replies with applied.
+    fn supervisor(file: &Path, running: ArunConfig, applied: bool) -> Supervisor {
+        let (sx, mut rx) = mpsc::channel::<RunnerUpdate>(3);
+        tokio::spawn(async move {
+            while let Some(update) = rx.recv().await {
+                if let RunnerUpdate::Config(_, reply) = update {
+                    let _ = reply.send(applied);
+                }
+            }
+        });
+
+        Supervisor {
+            runners: vec![],
+            registry: AppRegistry::default(),
+            paths: vec![file.display().to_string()],
+            monitor_interval: None,
+            resource_defaults: ResourceDefaults::default(),
+            policy: Policy::default(),
+            configs: HashMap::from([(running.appid(), running)]),
+            updates: HashMap::from([("sys.a".to_string(), sx)]),
+            shutting_down: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn reload_keeps_the_config_the_runner_rejects() {
+        let file = std::env::temp_dir().join(format!("arun-reload-{}.json", std::process::id()));
+        let mut changed = serde_json::to_value(config("a", &[])).unwrap();
+        changed["version"] = json!("0.2");
+        fs::write(&file, changed.to_string()).unwrap();
+        let changed: ArunConfig = serde_json::from_value(changed).unwrap();
+
+        let mut rejected = supervisor(&file, config("a", &[]), false);
+        rejected.reload(&mut JoinSet::new()).await;
+
+        let mut applied = supervisor(&file, config("a", &[]), true);
+        applied.reload(&mut JoinSet::new()).await;
+        let _ = fs::remove_file(&file);
+
+        assert_eq!(rejected.configs["sys.a"], config("a", &[]));
+        assert_eq!(applied.configs["sys.a"], changed);
+    }
+
+    #[test]
+    fn apps_refused_by_the_policy_are_left_out() {
+        let policy: Policy = serde_json::from_value(json!({"allow_privilege": false})).unwrap();
+        let app = |name: &str, app_type: &str, privilege: bool| {
+            let mut c = serde_json::to_value(config(name, &[])).unwrap();
+            c["app_type"] = json!(app_type);
+            c["privilege"] = json!(privilege);
+            serde_json::from_value::<ArunConfig>(c).unwrap()
+        };
+
+        let allowed = Supervisor::allowed(
+            vec![
+                app("weston", "Sys", true),
+                app("game", "User", true),
+                app("navi", "User", false),
+            ],
+            &policy,
+        )
+        .unwrap();
+
+        assert_eq!(
+            allowed.iter().map(|c| c.appid()).collect::<Vec<String>>(),
+            ["sys.weston", "user.navi"]
+        );
+    }
+
+    fn order(configs: Vec<ArunConfig>) -> Vec<String> {
+        Supervisor::sort_by_dependency(configs)
+            .unwrap()
+            .iter()
+            .map(|c| c.appid())
+            .collect()
+    }
+
+    #[test]
+    fn sort_linear_chain() {
+        assert_eq!(
+            order(vec![
+                config("c", &["b"]),
+                config("b", &["a"]),
+                config("a", &[]),
+            ]),
+            ["sys.a", "sys.b", "sys.c"]
+        );
+    }
+
+    #[test]
+    fn sort_diamond() {
+        let sorted = order(vec![
+            config("d", &["b", "c"]),
+            config("c", &["a"]),
+            config("b", &["a"]),
+            config("a", &[]),
+        ]);
+
+        let pos = |a: &str| sorted.iter().position(|s| s == a).unwrap();
+        assert_eq!(pos("sys.a"), 0);
+        assert!(pos("sys.b") < pos("sys.d"));
+        assert!(pos("sys.c") < pos("sys.d"));
+    }
+
+    #[test]
+    fn sort_keeps_independent_apps_in_order() {
+        assert_eq!(
+            order(vec![config("b", &[]), config("a", &[])]),
+            ["sys.b", "sys.a"]
+        );
+    }
+
+    #[test]
+    fn sort_rejects_cycle() {
+        let e = Supervisor::sort_by_dependency(vec![
+            config("a", &["c"]),
+            config("b", &["a"]),
+            config("c", &["b"]),
+            config("d", &[]),
+        ])
+        .unwrap_err();
+
+        assert!(format!("{:?}", e).contains("Circular dependency found among sys.a, sys.b, sys.c"));
+    }
+
+    #[test]
+    fn unknown_dependencies_are_reported() {
+        let configs = vec![config("a", &["missing"]), config("b", &["a"])];
+
+        assert_eq!(
+            Supervisor::unknown_dependencies(&configs),
+            ["sys.a depends on unknown app sys.missing"]
+        );
+    }
+
+    #[test]
+    fn sort_ignores_unknown_dependency() {
+        let sorted =
+            Supervisor::sort_by_dependency(vec![config("b", &["a"]), config("a", &["missing"])])
+                .unwrap();
+
+        assert_eq!(
+            sorted.iter().map(|c| c.appid()).collect::<Vec<String>>(),
+            ["sys.a", "sys.b"]
+        );
+    }
+}
@@ -0,0 +1,316 @@
+#[allow(unused)]
+use {
+    super::{
+        arun_config::ConfigFormat,
+        interpolate::{ENVIRONMENT_FILES, ENV_FILE},
+    },
+    arunlib::arun_error::ArunError,
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    serde_json::{Map, Value},
+    std::{
+        fs,
+        path::{Path, PathBuf},
+    },
+};
+
+// Field naming the templates an app or template inherits from, a name or a list of names.
+pub const EXTENDS: &str = "extends";
+
+// Templates referred by name are searched in this directory next to the config file.
+pub const TEMPLATE_DIR: &str = "templates";
+const TEMPLATE_EXTENSIONS: [&str; 4] = ["json", "toml", "yaml", "yml"];
+
+// Lists which hold the command of the app are replaced rather than merged.
+const REPLACED_LISTS: [&str; 2] = ["cmd", "entrypoint"];
+
+// Fields holding paths relative to the file they are written in.
+const PATH_FIELDS: [&str; 2] = [ENV_FILE, ENVIRONMENT_FILES];
+
+// The paths inherited from a template are relative to the template rather than to the
+// app, make them absolute before merging. A path starting with a variable is left as it
+// is, it is usually absolute once interpolated.
+fn absolute_paths(fields: &mut Map<String, Value>, dir: &Path) {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let absolute = |v: &mut Value| {
+        if let Value::String(s) = v {
+            if !s.starts_with('$') && Path::new(s.as_str()).is_relative() {
+                *s = dir.join(s.as_str()).to_string_lossy().to_string();
+            }
+        }
+    };
+
+    for f in PATH_FIELDS {
+        match fields.get_mut(f) {
+            Some(Value::Array(a)) => a.iter_mut().for_each(absolute),
+            Some(v) => absolute(v),
+            None => {}
+        }
+    }
+}
+
+// Entries of a merged list with the same key replace each other.
+fn list_key<'a>(field: &str, entry: &'a str) -> &'a str {
+    match field {
+        "environments" => entry.split_once('=').map_or(entry, |(k, _)| k),
+        // host:container[:options]
+        "binds" => entry.split(':').nth(1).unwrap_or(entry),
+        _ => entry,
+    }
+}
+
+// Inherited entries come first. An entry of the same key replaces the inherited one,
+// e.g. "DISPLAY=:1" replaces "DISPLAY=:0" in environments, and "!key" removes it, e.g.
+// "!gui" in features.
+pub fn merge_list(field: &str, base: Vec<Value>, list: Vec<Value>) -> Vec<Value> {
+    let mut merged = base;
+
+    for entry in list {
+        match entry.as_str() {
+            Some(s) if s.starts_with('!') => {
+                let key = list_key(field, &s[1..]);
+                merged.retain(|e| e.as_str().is_none_or(|b| list_key(field, b) != key));
+            }
+            Some(s) => {
+                let key = list_key(field, s);
+                match merged
+                    .iter_mut()
+                    .find(|e| e.as_str().is_some_and(|b| list_key(field, b) == key))
+                {
+                    Some(e) => *e = entry,
+                    None => merged.push(entry),
+                }
+            }
+            None => {
+                if !merged.contains(&entry) {
+                    merged.push(entry);
+                }
+            }
+        }
+    }
+
+    merged
+}
+
+// Fields override the inherited ones as a whole, except lists which are merged.
+fn merge(base: Map<String, Value>, fields: Map<String, Value>) -> Map<String, Value> {
+    let mut merged = base;
+
+    for (k, v) in fields {
+        let v = match (merged.remove(&k), v) {
+            (Some(Value::Array(b)), Value::Array(l)) if !REPLACED_LISTS.contains(&k.as_str()) => {
+                Value::Array(merge_list(&k, b, l))
+            }
+            (_, v) => v,
+        };
+
+        merged.insert(k, v);
+    }
+
+    merged
+}
+
+// A name with a path separator or an extension is a path relative to dir, the directory
+// of the file referring to it. Otherwise it is looked up as <name>.{json,toml,yaml,yml}
+// in template_dir, the templates directory next to the config file.
+fn locate(name: &str, dir: &Path, template_dir: &Path) -> Result<PathBuf, ArunError> {
+    let path = Path::new(name);
+
+    let candidates: Vec<PathBuf> = if name.contains('/') || path.extension().is_some() {
+        vec![dir.join(path)]
+    } else {
+        TEMPLATE_EXTENSIONS
+            .iter()
+            .map(|e| template_dir.join(format!("{}.{}", name, e)))
+            .collect()
+    };
+
+    candidates
+        .into_iter()
+        .find(|p| p.is_file())
+        .ok_or(ArunError::InvalidValue)
+        .into_report()
+        .attach_printable(format!(
+            "Template {} not found in {}",
+            name,
+            template_dir.display()
+        ))
+}
+
+fn load(path: &Path) -> Result<Value, ArunError> {
+    let text = fs::read_to_string(path)
+        .into_report()
+        .change_context(ArunError::IOError)
+        .attach_printable(format!("Failed to read template {}", path.display()))?;
+
+    ConfigFormat::detect(path, &text)
+        .parse_value(&text)
+        .attach_printable(format!("Invalid template {}", path.display()))
+}
+
+fn resolve_chain(
+    value: Value,
+    dir: &Path,
+    template_dir: &Path,
+    chain: &mut Vec<PathBuf>,
+    templates: &mut Vec<PathBuf>,
+) -> Result<Value, ArunError> {
+    let mut fields = match value {
+        Value::Object(m) => m,
+        v => return Ok(v),
+    };
+
+    let names: Vec<String> = match fields.remove(EXTENDS) {
+        None => vec![],
+        Some(Value::String(s)) => vec![s],
+        Some(Value::Array(a)) if a.iter().all(|v| v.is_string()) => a
+            .into_iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect(),
+        Some(v) => {
+            return Err(
+                Report::new(ArunError::InvalidValue).attach_printable(format!(
+                    "Invalid {}: {}, expected a template name or a list of names",
+                    EXTENDS, v
+                )),
+            )
+        }
+    };
+
+    // Templates listed later override the earlier ones.
+    let mut base = Map::new();
+    for name in names {
+        let path = locate(&name, dir, template_dir)?;
+
+        if chain.contains(&path) {
+            let cycle: Vec<String> = chain
+                .iter()
+                .chain([&path])
+                .map(|p| p.display().to_string())
+                .collect();
+
+            return Err(Report::new(ArunError::InvalidValue)
+                .attach_printable(format!("Template cycle: {}", cycle.join(" -> "))));
+        }
+
+        let template_path = path.parent().unwrap_or_else(|| Path::new("."));
+
+        if !templates.contains(&path) {
+            templates.push(path.clone());
+        }
+
+        chain.push(path.clone());
+        let template = load(&path)?;
+        let template = resolve_chain(template, template_path, template_dir, chain, templates)?;
+        chain.pop();
+
+        match template {
+            Value::Object(mut t) => {
+                absolute_paths(&mut t, template_path);
+                base = merge(base, t);
+            }
+            _ => {
+                return Err(Report::new(ArunError::InvalidValue)
+                    .attach_printable(format!("Template {} is not a table", path.display())))
+            }
+        }
+    }
+
+    Ok(Value::Object(merge(base, fields)))
+}
+
+// Merge the app over the templates it extends, recursively.
+pub fn resolve(value: Value, dir: &Path) -> Result<Value, ArunError> {
+    resolve_with_templates(value, dir).map(|(v, _)| v)
+}
+
+// Like resolve(), also returning the template files merged in the order they are extended.
+pub fn resolve_with_templates(
+    value: Value,
+    dir: &Path,
+) -> Result<(Value, Vec<PathBuf>), ArunError> {
+    let mut templates = vec![];
+    let value = resolve_chain(
+        value,
+        dir,
+        &dir.join(TEMPLATE_DIR),
+        &mut vec![],
+        &mut templates,
+    )?;
+
+    Ok((value, templates))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    fn list(v: Value) -> Vec<Value> {
+        match v {
+            Value::Array(a) => a,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn merge_list_appends_and_replaces_by_key() {
+        assert_eq!(
+            merge_list(
+                "environments",
+                list(json!(["DISPLAY=:0", "LANG=C"])),
+                list(json!(["DISPLAY=:1", "TZ=UTC"]))
+            ),
+            list(json!(["DISPLAY=:1", "LANG=C", "TZ=UTC"]))
+        );
+        assert_eq!(
+            merge_list(
+                "binds",
+                list(json!(["/data:/data:ro", "/tmp:/tmp"])),
+                list(json!(["/srv/data:/data"]))
+            ),
+            list(json!(["/srv/data:/data", "/tmp:/tmp"]))
+        );
+        assert_eq!(
+            merge_list(
+                "features",
+                list(json!(["gui"])),
+                list(json!(["gui", "wayland"]))
+            ),
+            list(json!(["gui", "wayland"]))
+        );
+    }
+
+    #[test]
+    fn merge_list_removes_entries() {
+        assert_eq!(
+            merge_list(
+                "features",
+                list(json!(["gui", "wayland"])),
+                list(json!(["!gui"]))
+            ),
+            list(json!(["wayland"]))
+        );
+        assert_eq!(
+            merge_list(
+                "environments",
+                list(json!(["DISPLAY=:0", "LANG=C"])),
+                list(json!(["!DISPLAY"]))
+            ),
+            list(json!(["LANG=C"]))
+        );
+    }
+
+    #[test]
+    fn merge_list_keeps_other_values_once() {
+        assert_eq!(
+            merge_list(
+                "port_bindings",
+                list(json!([{"port": "80/tcp", "host": []}])),
+                list(json!([{"port": "80/tcp", "host": []}, {"port": "443/tcp", "host": []}]))
+            ),
+            list(json!([{"port": "80/tcp", "host": []}, {"port": "443/tcp", "host": []}]))
+        );
+    }
+}
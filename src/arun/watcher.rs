@@ -0,0 +1,178 @@
+#[allow(unused)]
+use {
+    super::{arun_config::ConfigFormat, template},
+    arunlib::arun_error::ArunError,
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    futures::StreamExt,
+    inotify::{EventStream, Inotify, WatchDescriptor, WatchMask},
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    std::{
+        collections::{HashMap, HashSet},
+        path::{Path, PathBuf},
+    },
+    tokio::time::{timeout, Duration},
+};
+
+// Editors save a file with several writes and renames, the changes coming within this
+// delay are reported once.
+const SETTLE_DELAY_MS: u64 = 500;
+
+// The .env file next to the configs is watched even if no app reads it yet.
+const DOT_ENV: &str = ".env";
+
+fn watch_mask() -> WatchMask {
+    WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::MOVED_FROM | WatchMask::DELETE
+}
+
+// An absolute path to compare with the paths of the changed files, the file itself may
+// not exist yet.
+fn canonical(path: &Path) -> PathBuf {
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new("."));
+
+    match (dir.canonicalize(), path.file_name()) {
+        (Ok(d), Some(n)) => d.join(n),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub struct ConfigWatcher {
+    events: EventStream<[u8; 4096]>,
+    // The watched directories, the events only carry the file names.
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    // The files named in the env_file and environment_files of the apps.
+    env_files: HashSet<PathBuf>,
+    // A change is seen but not reported yet, kept if waiting for it is cancelled.
+    pending: bool,
+}
+
+impl ConfigWatcher {
+    // Watch the directories holding the config files rather than the files themselves,
+    // so that a file replaced by a rename is still watched.
+    pub fn new(paths: &[String]) -> Result<Self, ArunError> {
+        let mut dirs = HashSet::new();
+
+        for p in paths {
+            let path = Path::new(p);
+            let dir = if path.is_dir() {
+                path.to_path_buf()
+            } else {
+                path.parent()
+                    .filter(|d| !d.as_os_str().is_empty())
+                    .unwrap_or_else(|| Path::new("."))
+                    .to_path_buf()
+            };
+
+            let templates = dir.join(template::TEMPLATE_DIR);
+            if templates.is_dir() {
+                dirs.insert(templates);
+            }
+
+            dirs.insert(dir);
+        }
+
+        let inotify = Inotify::init()
+            .into_report()
+            .change_context(ArunError::IOError)
+            .attach_printable("Failed to initialize inotify")?;
+
+        let events = inotify
+            .into_event_stream([0_u8; 4096])
+            .into_report()
+            .change_context(ArunError::IOError)?;
+
+        let mut watcher = Self {
+            events,
+            dirs: HashMap::new(),
+            env_files: HashSet::new(),
+            pending: false,
+        };
+
+        for d in &dirs {
+            watcher.watch(d)?;
+        }
+
+        Ok(watcher)
+    }
+
+    fn watch(&mut self, dir: &Path) -> Result<(), ArunError> {
+        let wd = self
+            .events
+            .watches()
+            .add(dir, watch_mask())
+            .into_report()
+            .change_context(ArunError::IOError)
+            .attach_printable(format!("Failed to watch {}", dir.display()))?;
+
+        jdebug!("Watching {}", dir.display());
+        self.dirs
+            .insert(wd, dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf()));
+        Ok(())
+    }
+
+    // Watch the env files read by the apps, their directories are watched in addition to
+    // the config directories. Called again whenever the configs are reloaded.
+    pub fn watch_env_files(&mut self, env_files: Vec<PathBuf>) {
+        self.env_files = env_files.iter().map(|f| canonical(f)).collect();
+
+        let dirs: HashSet<PathBuf> = self
+            .env_files
+            .iter()
+            .filter_map(|f| f.parent().map(|d| d.to_path_buf()))
+            .filter(|d| !self.dirs.values().any(|w| w == d))
+            .collect();
+
+        for d in dirs {
+            if let Err(e) = self.watch(&d) {
+                jwarn!(
+                    "Changes of the env files in {} are missed: {:?}",
+                    d.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    // Changes of other files, like swap files of editors, are ignored.
+    fn is_config(&self, wd: &WatchDescriptor, name: &Path) -> bool {
+        ConfigFormat::from_extension(name).is_some()
+            || name.file_name().is_some_and(|n| n == DOT_ENV)
+            || self
+                .dirs
+                .get(wd)
+                .is_some_and(|d| self.env_files.contains(&d.join(name)))
+    }
+
+    // Wait until a config file has been changed and the changes have settled.
+    pub async fn changed(&mut self) -> Result<(), ArunError> {
+        while !self.pending {
+            let event = self
+                .events
+                .next()
+                .await
+                .ok_or(ArunError::IOError)
+                .into_report()
+                .attach_printable("inotify stream closed")?
+                .into_report()
+                .change_context(ArunError::IOError)?;
+
+            if let Some(name) = event.name {
+                if self.is_config(&event.wd, Path::new(&name)) {
+                    jdebug!("Config file {:?} changed", name);
+                    self.pending = true;
+                }
+            }
+        }
+
+        while let Ok(Some(_)) =
+            timeout(Duration::from_millis(SETTLE_DELAY_MS), self.events.next()).await
+        {}
+
+        self.pending = false;
+        Ok(())
+    }
+}
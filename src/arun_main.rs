@@ -3,23 +3,61 @@ pub mod arun;
 #[allow(unused)]
 use {
     arun::{
-        arun_config::{AppType, ArunConfig},
+        arun_config::{AppType, ArunConfig, ConfigFormat, ResourceDefaults},
+        drm,
+        policy::Policy,
         runner::Runner,
+        supervisor::Supervisor,
     },
     arunlib::arun_error::ArunError,
-    clap::Parser,
+    clap::{Parser, Subcommand},
     error_stack::{IntoReport, Report, Result, ResultExt},
     jlogger_tracing::{
         jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
     },
-    std::fs,
+    std::{fs, path::Path, str::FromStr},
 };
 
+#[derive(Subcommand, Debug)]
+enum ArunCmd {
+    /// Check config files, manifests or config directories without running them
+    Validate {
+        #[clap(required = true)]
+        files: Vec<String>,
+    },
+    /// Convert a config file or manifest between json, toml and yaml
+    Convert {
+        file: String,
+
+        /// Output format: json, toml or yaml
+        #[clap(long = "to")]
+        to: String,
+
+        /// Write to the file instead of stdout
+        #[clap(short = 'o', long = "output")]
+        output: Option<String>,
+    },
+    /// Print the configs with the templates they extend resolved
+    Render {
+        #[clap(required = true)]
+        files: Vec<String>,
+
+        /// Output format: json, toml or yaml
+        #[clap(long = "to", default_value_t = String::from("json"))]
+        to: String,
+    },
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true)]
 struct Cli {
-    #[clap(short = 'c', long = "config")]
-    config: String,
+    #[clap(subcommand)]
+    cmd: Option<ArunCmd>,
+
+    /// App config file, manifest or directory of json/toml/yaml configs. Can be repeated.
+    #[clap(short = 'c', long = "config", required = true)]
+    config: Vec<String>,
 
     #[clap(short = 'l',long="log-file", default_value_t=String::from("/tmp/arun.log"))]
     log: String,
@@ -27,10 +65,144 @@ struct Cli {
     #[clap(short = 'm', long = "monitor-interval")]
     monitor_interval: Option<u32>,
 
+    /// Default resource limits of Sys and User apps
+    #[clap(short = 'r', long = "resource-defaults", global = true)]
+    resource_defaults: Option<String>,
+
+    /// Policy restricting what User apps may request
+    #[clap(short = 'p', long = "policy", global = true)]
+    policy: Option<String>,
+
+    /// Root of the sys and dev trees the DRM devices are looked up from, for testing
+    #[clap(long = "drm-root", hidden = true)]
+    drm_root: Option<String>,
+
     #[clap(short, long, parse(from_occurrences))]
     verbose: usize,
 }
 
+// Printable attachments of a report, outermost first.
+fn diagnostics(report: &Report<ArunError>) -> Vec<String> {
+    report
+        .frames()
+        .filter_map(|f| {
+            f.downcast_ref::<String>()
+                .cloned()
+                .or_else(|| f.downcast_ref::<&str>().map(|s| s.to_string()))
+        })
+        .collect()
+}
+
+// Check the config files, printing what is wrong with them. Unknown dependencies are
+// only warned about, as the apps they name may be in files not validated together.
+fn validate(
+    files: &[String],
+    resource_defaults: &ResourceDefaults,
+    policy: &Policy,
+) -> Result<bool, ArunError> {
+    let mut valid = true;
+
+    for file in Supervisor::config_files(files)? {
+        match Supervisor::load_file(&file, None) {
+            Ok(configs) => {
+                for c in configs {
+                    println!("{}: {} OK", file.display(), c.appid());
+                    for w in c.security_warnings() {
+                        println!("    warning: {}", w);
+                    }
+                }
+            }
+            Err(e) => {
+                valid = false;
+                eprintln!("{}: {}", file.display(), e.current_context());
+                for d in diagnostics(&e) {
+                    eprintln!("    {}", d);
+                }
+            }
+        }
+    }
+
+    // Checks across files, like duplicated apps and dependencies.
+    if valid {
+        let configs = Supervisor::load_apps(files, None, resource_defaults).and_then(|c| {
+            for w in Supervisor::unknown_dependencies(&c) {
+                println!("warning: {}", w);
+            }
+            Supervisor::sort_by_dependency(c)
+        });
+
+        match configs {
+            Ok(configs) => {
+                for c in configs {
+                    if let Err(e) = policy.check(&c) {
+                        valid = false;
+                        eprintln!("{}: {}", c.appid(), e.current_context());
+                        for d in diagnostics(&e) {
+                            eprintln!("    {}", d);
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                valid = false;
+                for d in diagnostics(&e) {
+                    eprintln!("{}", d);
+                }
+            }
+        }
+    }
+
+    Ok(valid)
+}
+
+fn convert(file: &str, to: &str, output: Option<&str>) -> Result<(), ArunError> {
+    let to = ConfigFormat::from_str(to)
+        .into_report()
+        .attach_printable(format!("Unsupported format {}, use json, toml or yaml", to))?;
+
+    let configs = Supervisor::load_file(Path::new(file), None)?;
+    let mut text = ArunConfig::serialize_manifest(&configs, to)?;
+    if !text.ends_with('\n') {
+        text.push('\n');
+    }
+
+    match output {
+        Some(o) => fs::write(o, text)
+            .into_report()
+            .change_context(ArunError::IOError)
+            .attach_printable(format!("Failed to write {}", o)),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn render(
+    files: &[String],
+    to: &str,
+    resource_defaults: &ResourceDefaults,
+) -> Result<(), ArunError> {
+    let to = ConfigFormat::from_str(to)
+        .into_report()
+        .attach_printable(format!("Unsupported format {}, use json, toml or yaml", to))?;
+
+    let mut configs = vec![];
+    for file in Supervisor::config_files(files)? {
+        configs.append(&mut Supervisor::load_file(&file, None)?);
+    }
+
+    for c in configs.iter_mut() {
+        c.apply_resource_defaults(resource_defaults)?;
+    }
+
+    println!(
+        "{}",
+        ArunConfig::serialize_manifest(&configs, to)?.trim_end()
+    );
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), ArunError> {
     let cli = Cli::parse();
@@ -43,13 +215,37 @@ async fn main() -> Result<(), ArunError> {
 
     JloggerBuilder::new().max_level(max_level).build();
 
-    let json = fs::read_to_string(cli.config)
-        .into_report()
-        .change_context(ArunError::InvalidValue)?;
+    if let Some(root) = &cli.drm_root {
+        drm::set_root(root);
+    }
+
+    let resource_defaults = match &cli.resource_defaults {
+        Some(f) => ResourceDefaults::load(Path::new(f))?,
+        None => ResourceDefaults::default(),
+    };
+
+    let policy = match &cli.policy {
+        Some(f) => Policy::load(Path::new(f))?,
+        None => Policy::default(),
+    };
 
-    jdebug!("Config:\n{}", json);
+    match cli.cmd {
+        Some(ArunCmd::Validate { files }) => {
+            // The problems found are printed already, only the exit code is left.
+            if !validate(&files, &resource_defaults, &policy)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(ArunCmd::Convert { file, to, output }) => {
+            return convert(&file, &to, output.as_deref())
+        }
+        Some(ArunCmd::Render { files, to }) => return render(&files, &to, &resource_defaults),
+        None => {}
+    }
 
-    let mut runner = Runner::new(json.as_str(), cli.monitor_interval).await?;
+    let supervisor =
+        Supervisor::new(&cli.config, cli.monitor_interval, resource_defaults, policy).await?;
 
-    runner.run().await
+    supervisor.run().await
 }
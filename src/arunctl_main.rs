@@ -0,0 +1,309 @@
+#[allow(unused)]
+use {
+    arunlib::{
+        arun_error::ArunError,
+        ctlif_msg::{
+            ctrl_peer_name, ctrl_socket_path, ArunCtrlCmd, ArunCtrlRequest, ArunCtrlResponse,
+            RunnerState,
+        },
+    },
+    bollard::{container, container::LogOutput, Docker},
+    clap::{Parser, Subcommand},
+    error_stack::{IntoReport, Report, Result, ResultExt},
+    futures::StreamExt,
+    jlogger_tracing::{
+        jdebug, jerror, jinfo, jtrace, jwarn, JloggerBuilder, LevelFilter, LogTimeFormat,
+    },
+    std::{io::Write, process},
+    tokio::time::{timeout, Duration},
+};
+
+#[cfg(feature = "ctlif-ipcon")]
+use ipcon_sys::{ipcon::IPF_DEFAULT, ipcon_async::AsyncIpcon, ipcon_msg::IpconMsg};
+
+#[cfg(not(feature = "ctlif-ipcon"))]
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::UnixStream,
+};
+
+const EXIT_OK: i32 = 0;
+// The command was received but failed, the error is printed.
+const EXIT_FAILED: i32 = 1;
+// `status` only: the app is not running.
+const EXIT_NOT_RUNNING: i32 = 3;
+// arun could not be reached or did not reply in time.
+const EXIT_UNREACHABLE: i32 = 4;
+
+#[derive(Subcommand, Debug)]
+enum CtlCmd {
+    /// Start the app
+    Start { appid: String },
+    /// Stop the app
+    Stop { appid: String },
+    /// Stop and remove the container of the app
+    Remove { appid: String },
+    /// Pause the app
+    Pause { appid: String },
+    /// Unpause the app
+    Unpause { appid: String },
+    /// Stop and start the app again
+    Restart { appid: String },
+    /// Show the state of the app, exit with 3 if it is not running
+    Status { appid: String },
+    /// Show the docker inspect result of the app container
+    Inspect { appid: String },
+    /// Allow a quarantined app to be restarted again
+    ClearQuarantine { appid: String },
+    /// Show the logs of the app container
+    Logs {
+        appid: String,
+
+        #[clap(short = 'f', long = "follow")]
+        follow: bool,
+
+        #[clap(long = "tail", default_value_t = String::from("all"))]
+        tail: String,
+    },
+}
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about = "Control the apps run by arun", long_about = None)]
+#[clap(after_help = "Exit status: 0 on success, 1 if the command failed, \
+    3 if the app is not running (status), 4 if arun is not reachable.")]
+struct Cli {
+    #[clap(subcommand)]
+    cmd: CtlCmd,
+
+    /// Print the reply as json
+    #[clap(long = "json")]
+    json: bool,
+
+    /// Seconds to wait for the reply
+    #[clap(short = 't', long = "timeout", default_value_t = 30)]
+    timeout: u64,
+
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: usize,
+}
+
+#[cfg(not(feature = "ctlif-ipcon"))]
+async fn request(appid: &str, req: &ArunCtrlRequest) -> Result<ArunCtrlResponse, ArunError> {
+    let path = ctrl_socket_path(appid);
+
+    let stream = UnixStream::connect(&path)
+        .await
+        .into_report()
+        .change_context(ArunError::IOError)
+        .attach_printable(format!("Failed to connect to {}", path.display()))?;
+
+    let (reader, mut writer) = stream.into_split();
+
+    let mut buf = serde_json::to_vec(req)
+        .into_report()
+        .change_context(ArunError::InvalidValue)?;
+    buf.push(b'\n');
+
+    writer
+        .write_all(&buf)
+        .await
+        .into_report()
+        .change_context(ArunError::IOError)?;
+
+    let mut lines = BufReader::new(reader).lines();
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .into_report()
+        .change_context(ArunError::IOError)?
+    {
+        let response: ArunCtrlResponse = serde_json::from_str(&line)
+            .into_report()
+            .change_context(ArunError::InvalidValue)
+            .attach_printable(format!("Invalid reply: {}", line))?;
+
+        if response.id == req.id {
+            return Ok(response);
+        }
+    }
+
+    Err(ArunError::IOError)
+        .into_report()
+        .attach_printable(format!("{} closed without reply", path.display()))
+}
+
+#[cfg(feature = "ctlif-ipcon")]
+async fn request(appid: &str, req: &ArunCtrlRequest) -> Result<ArunCtrlResponse, ArunError> {
+    let peer = ctrl_peer_name(appid);
+    let name = format!("arunctl.{}", process::id());
+
+    let ih =
+        AsyncIpcon::new(Some(&name), Some(IPF_DEFAULT)).change_context(ArunError::IpconError)?;
+
+    let buf = serde_json::to_vec(req)
+        .into_report()
+        .change_context(ArunError::InvalidValue)?;
+
+    ih.send_unicast_msg(&peer, &buf)
+        .await
+        .change_context(ArunError::IpconError)
+        .attach_printable(format!("Failed to send to {}", peer))?;
+
+    loop {
+        if let IpconMsg::IpconMsgUser(m) = ih
+            .receive_msg()
+            .await
+            .change_context(ArunError::IpconError)?
+        {
+            if m.peer != peer {
+                continue;
+            }
+
+            let body = String::from_utf8_lossy(&m.buf);
+            let response: ArunCtrlResponse =
+                serde_json::from_str(body.trim().trim_end_matches('\0'))
+                    .into_report()
+                    .change_context(ArunError::InvalidValue)
+                    .attach_printable(format!("Invalid reply: {}", body))?;
+
+            if response.id == req.id {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+fn print_response(cmd: &ArunCtrlCmd, response: &ArunCtrlResponse, json: bool) {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(response).unwrap_or_default()
+        );
+        return;
+    }
+
+    if let Some(e) = &response.error {
+        eprintln!("{}: {}", response.appid, e);
+        return;
+    }
+
+    match cmd {
+        ArunCtrlCmd::Inspect => println!(
+            "{}",
+            serde_json::to_string_pretty(&response.inspect).unwrap_or_default()
+        ),
+        _ => {
+            println!(
+                "{}: {} (target: {}), health: {}{}",
+                response.appid,
+                response.state,
+                response.target_state,
+                response.health,
+                if response.quarantined {
+                    ", quarantined"
+                } else {
+                    ""
+                }
+            );
+            println!("image: {}", response.image);
+        }
+    }
+}
+
+async fn logs(appid: &str, follow: bool, tail: String) -> Result<(), ArunError> {
+    let docker = Docker::connect_with_socket_defaults()
+        .into_report()
+        .change_context(ArunError::DockerErr)?;
+
+    let options = container::LogsOptions::<String> {
+        follow,
+        stdout: true,
+        stderr: true,
+        tail,
+        ..Default::default()
+    };
+
+    let mut stream = docker.logs(appid, Some(options));
+    while let Some(output) = stream.next().await {
+        match output.into_report().change_context(ArunError::DockerErr)? {
+            LogOutput::StdErr { message } => {
+                let _ = std::io::stderr().write_all(&message);
+            }
+            o => {
+                let _ = std::io::stdout().write_all(&o.into_bytes());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn run(cli: Cli) -> i32 {
+    let (appid, cmd) = match cli.cmd {
+        CtlCmd::Start { appid } => (appid, ArunCtrlCmd::Start),
+        CtlCmd::Stop { appid } => (appid, ArunCtrlCmd::Stop),
+        CtlCmd::Remove { appid } => (appid, ArunCtrlCmd::Remove),
+        CtlCmd::Pause { appid } => (appid, ArunCtrlCmd::Pause),
+        CtlCmd::Unpause { appid } => (appid, ArunCtrlCmd::Unpause),
+        CtlCmd::Restart { appid } => (appid, ArunCtrlCmd::Restart),
+        CtlCmd::Status { appid } => (appid, ArunCtrlCmd::Status),
+        CtlCmd::Inspect { appid } => (appid, ArunCtrlCmd::Inspect),
+        CtlCmd::ClearQuarantine { appid } => (appid, ArunCtrlCmd::ClearQuarantine),
+        CtlCmd::Logs {
+            appid,
+            follow,
+            tail,
+        } => {
+            return match logs(&appid, follow, tail).await {
+                Ok(()) => EXIT_OK,
+                Err(e) => {
+                    eprintln!("{:?}", e);
+                    EXIT_FAILED
+                }
+            };
+        }
+    };
+
+    let req = ArunCtrlRequest {
+        id: process::id() as u64,
+        cmd,
+    };
+
+    let response = match timeout(Duration::from_secs(cli.timeout), request(&appid, &req)).await {
+        Ok(Ok(r)) => r,
+        Ok(Err(e)) => {
+            eprintln!("{:?}", e);
+            return EXIT_UNREACHABLE;
+        }
+        Err(_) => {
+            eprintln!("No reply from {} in {}s", appid, cli.timeout);
+            return EXIT_UNREACHABLE;
+        }
+    };
+
+    print_response(&req.cmd, &response, cli.json);
+
+    if response.error.is_some() {
+        EXIT_FAILED
+    } else if matches!(req.cmd, ArunCtrlCmd::Status) && response.state != RunnerState::Running {
+        EXIT_NOT_RUNNING
+    } else {
+        EXIT_OK
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    let max_level = match cli.verbose {
+        1 => LevelFilter::INFO,
+        2 => LevelFilter::DEBUG,
+        3 => LevelFilter::TRACE,
+        _ => LevelFilter::WARN,
+    };
+
+    JloggerBuilder::new().max_level(max_level).build();
+
+    process::exit(run(cli).await);
+}
@@ -5,6 +5,10 @@ pub enum ArunError {
     InvalidValue,
     DockerErr,
     ConflictedWithOther,
+    Unhealthy,
+    Quarantined,
+    PolicyViolation,
+    DigestMismatch,
     #[cfg(feature = "ctlif-ipcon")]
     IpconError,
 
@@ -18,6 +22,10 @@ impl Display for ArunError {
             ArunError::InvalidValue => "Invalid Parameter",
             ArunError::DockerErr => "Docker error",
             ArunError::ConflictedWithOther => "Another app with same name exists",
+            ArunError::Unhealthy => "Health check failed",
+            ArunError::Quarantined => "App is quarantined",
+            ArunError::PolicyViolation => "Not allowed by the policy",
+            ArunError::DigestMismatch => "Image does not match the pinned digest",
 
             #[cfg(feature = "ctlif-ipcon")]
             ArunError::IpconError => "Ipcon error",
@@ -0,0 +1,139 @@
+#[allow(unused)]
+use {
+    crate::arun_error::ArunError,
+    serde::{Deserialize, Serialize},
+    std::{fmt::Display, path::PathBuf, str::FromStr},
+};
+
+// Directory of the unix sockets used as control interface when ipcon is not available.
+pub const CTRL_SOCKET_DIR: &str = "/run/arun";
+
+// Unix socket of the control interface of an app.
+pub fn ctrl_socket_path(appid: &str) -> PathBuf {
+    PathBuf::from(CTRL_SOCKET_DIR).join(format!("{}.sock", appid))
+}
+
+// Ipcon peer name of the control interface of an app.
+pub fn ctrl_peer_name(appid: &str) -> String {
+    format!("arun.{}", appid)
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RunnerState {
+    NonExist,
+    Created,
+    Running,
+    Restarting,
+    Exited,
+    Paused,
+    Dead,
+}
+
+impl Display for RunnerState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state_str = match self {
+            RunnerState::NonExist => "NonExist",
+            RunnerState::Created => "Created",
+            RunnerState::Running => "Running",
+            RunnerState::Restarting => "Restarting",
+            RunnerState::Exited => "Exited",
+            RunnerState::Dead => "Dead",
+            RunnerState::Paused => "Paused",
+        };
+
+        write!(f, "{}", state_str)
+    }
+}
+
+impl FromStr for RunnerState {
+    type Err = ArunError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(RunnerState::Created),
+            "running" => Ok(RunnerState::Running),
+            "restarting" => Ok(RunnerState::Restarting),
+            "exited" => Ok(RunnerState::Exited),
+            "paused" => Ok(RunnerState::Paused),
+            "dead" => Ok(RunnerState::Dead),
+            "nonExist" => Ok(RunnerState::NonExist),
+            _ => Err(ArunError::Unknown),
+        }
+    }
+}
+
+// Health of a running app as reported by its health check. An app without health
+// check is regarded as healthy while it is running.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RunnerHealth {
+    Unknown,
+    Healthy,
+    Unhealthy,
+}
+
+impl Display for RunnerHealth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let health_str = match self {
+            RunnerHealth::Unknown => "Unknown",
+            RunnerHealth::Healthy => "Healthy",
+            RunnerHealth::Unhealthy => "Unhealthy",
+        };
+
+        write!(f, "{}", health_str)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub enum ArunCtrlCmd {
+    Start,
+    Stop,
+    Remove,
+    Pause,
+    Unpause,
+    Restart,
+    Status,
+    Inspect,
+    ClearQuarantine,
+    Quit,
+    Invalid,
+}
+
+impl Display for ArunCtrlCmd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            ArunCtrlCmd::Start => "ArunCtrCmd::Start",
+            ArunCtrlCmd::Stop => "ArunCtrCmd::Stop",
+            ArunCtrlCmd::Remove => "ArunCtrCmd::Remove",
+            ArunCtrlCmd::Pause => "ArunCtrCmd::Pause",
+            ArunCtrlCmd::Unpause => "ArunCtrCmd::Unpause",
+            ArunCtrlCmd::Restart => "ArunCtrCmd::Restart",
+            ArunCtrlCmd::Status => "ArunCtrCmd::Status",
+            ArunCtrlCmd::Inspect => "ArunCtrCmd::Inspect",
+            ArunCtrlCmd::ClearQuarantine => "ArunCtrCmd::ClearQuarantine",
+            ArunCtrlCmd::Quit => "ArunCtrCmd::Quit",
+            ArunCtrlCmd::Invalid => "ArunCtrlCmd::Invalid",
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+// A command with a request id, answered by an ArunCtrlResponse with the same id.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ArunCtrlRequest {
+    pub id: u64,
+    pub cmd: ArunCtrlCmd,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ArunCtrlResponse {
+    pub id: u64,
+    pub appid: String,
+    pub image: String,
+    pub state: RunnerState,
+    pub target_state: RunnerState,
+    pub health: RunnerHealth,
+    pub quarantined: bool,
+    pub error: Option<String>,
+    pub inspect: Option<serde_json::Value>,
+}
@@ -1,2 +1,3 @@
 pub mod arun_error;
+pub mod ctlif_msg;
 pub mod utils;